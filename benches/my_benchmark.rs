@@ -1,7 +1,7 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, criterion_main, Criterion};
 use std::future::Future;
 
-use async_executor::{Executor, Task};
+use async_executor::{Executor, LocalExecutor, Task};
 use futures_lite::{future, prelude::*};
 
 const TASKS: usize = 300;
@@ -53,6 +53,7 @@ fn spawn_many(b: &mut criterion::Bencher) {
 }
 
 fn spawn_executors_recursively(b: &mut criterion::Bencher) {
+    #[allow(clippy::manual_async_fn)]
     fn go(i: usize) -> impl Future<Output = ()> + Send + 'static {
         async move {
             if i != 0 {
@@ -114,7 +115,7 @@ fn ping_pong(b: &mut criterion::Bencher) {
     run(|| {
         b.iter(move || {
             future::block_on(async {
-                for i in 0..NUM_PINGS {
+                for _ in 0..NUM_PINGS {
                     let (os_send, os_recv) = async_oneshot::oneshot();
                     send.send(os_send).await.unwrap();
                     os_recv.await.unwrap();
@@ -150,12 +151,11 @@ fn context_switch_quiet(b: &mut criterion::Bencher) {
 fn context_switch_busy(b: &mut criterion::Bencher) {
     let (send, mut recv) = async_channel::bounded::<usize>(1);
     let mut tasks: Vec<Task<Option<()>>> = vec![];
-    for num in 0..TASKS / 10 {
+    for _ in 0..TASKS / 10 {
         let old_recv = recv.clone();
         let (new_send, new_recv) = async_channel::bounded(1);
         tasks.push(EX.spawn(async move {
             loop {
-                // eprintln!("forward {}", num);
                 new_send.send(old_recv.recv().await.ok()?).await.ok()?;
             }
         }));
@@ -180,6 +180,47 @@ fn context_switch_busy(b: &mut criterion::Bencher) {
     });
 }
 
+fn local_spawn_one(b: &mut criterion::Bencher) {
+    let ex = LocalExecutor::new();
+    b.iter(move || {
+        future::block_on(ex.run(async { ex.spawn(async {}).await }));
+    });
+}
+
+fn local_spawn_many(b: &mut criterion::Bencher) {
+    let ex = LocalExecutor::new();
+    b.iter(move || {
+        future::block_on(ex.run(async {
+            let mut tasks = Vec::new();
+            for _ in 0..LIGHT_TASKS {
+                tasks.push(ex.spawn(async {}));
+            }
+            for task in tasks {
+                task.await;
+            }
+        }));
+    });
+}
+
+fn local_yield_now(b: &mut criterion::Bencher) {
+    let ex = LocalExecutor::new();
+    b.iter(move || {
+        future::block_on(ex.run(async {
+            let mut tasks = Vec::new();
+            for _ in 0..TASKS {
+                tasks.push(ex.spawn(async move {
+                    for _ in 0..STEPS {
+                        future::yield_now().await;
+                    }
+                }));
+            }
+            for task in tasks {
+                task.await;
+            }
+        }));
+    });
+}
+
 fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("create", create);
     c.bench_function("spawn_one", spawn_one);
@@ -189,6 +230,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("spawn_executors_recursively", spawn_executors_recursively);
     c.bench_function("context_switch_quiet", context_switch_quiet);
     c.bench_function("context_switch_busy", context_switch_busy);
+    c.bench_function("local_spawn_one", local_spawn_one);
+    c.bench_function("local_spawn_many", local_spawn_many);
+    c.bench_function("local_yield_now", local_yield_now);
 }
 
 criterion_group!(benches, criterion_benchmark);
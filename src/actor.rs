@@ -0,0 +1,730 @@
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::task::{Poll, Waker};
+
+use futures_lite::future;
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::CallOnDrop;
+
+/// A task that owns its state and handles messages sent to its mailbox one at a time.
+///
+/// Actors are spawned with [`Executor::spawn_actor()`][crate::Executor::spawn_actor], which
+/// returns the actor's first [`Addr`]. The actor stops once all of its addresses are dropped and
+/// its mailbox is empty.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Executor, Reply};
+///
+/// enum Message {
+///     Add(u64),
+///     Get(Reply<u64>),
+/// }
+///
+/// struct Counter(u64);
+///
+/// impl Actor for Counter {
+///     type Message = Message;
+///
+///     async fn handle(&mut self, message: Message) {
+///         match message {
+///             Message::Add(n) => self.0 += n,
+///             Message::Get(reply) => reply.send(self.0),
+///         }
+///     }
+/// }
+///
+/// let ex = Executor::new();
+/// let addr = ex.spawn_actor(Counter(0), 16);
+///
+/// ex.block_on(async {
+///     addr.send(Message::Add(2)).await.unwrap();
+///     addr.send(Message::Add(3)).await.unwrap();
+///     assert_eq!(addr.ask(Message::Get).await, Some(5));
+/// });
+/// ```
+pub trait Actor: Send {
+    /// The type of messages the actor handles.
+    type Message: Send;
+
+    /// Handles a message.
+    fn handle(&mut self, message: Self::Message) -> impl Future<Output = ()> + Send;
+
+    /// Called after the last address is dropped and the mailbox is empty.
+    ///
+    /// Not called if the actor panics or the executor gets dropped.
+    fn stopped(&mut self) -> impl Future<Output = ()> + Send {
+        async {}
+    }
+}
+
+/// A handle for sending messages to an [`Actor`].
+///
+/// Addresses can be cloned. The actor stops once all of them are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Executor};
+///
+/// struct Printer;
+///
+/// impl Actor for Printer {
+///     type Message = String;
+///
+///     async fn handle(&mut self, message: String) {
+///         println!("{}", message);
+///     }
+/// }
+///
+/// let ex = Executor::new();
+/// let addr = ex.spawn_actor(Printer, 16);
+///
+/// addr.try_send("Hello world".to_string()).unwrap();
+/// ```
+pub struct Addr<A: Actor> {
+    mailbox: Arc<Mailbox<A::Message>>,
+
+    /// The ID of the actor's task.
+    id: u64,
+}
+
+impl<A: Actor> Addr<A> {
+    /// Creates the first address of an actor.
+    pub(crate) fn new(mailbox: Arc<Mailbox<A::Message>>, id: u64) -> Addr<A> {
+        Addr { mailbox, id }
+    }
+
+    /// Returns the ID of the actor's task.
+    ///
+    /// The actor is listed under this ID in
+    /// [`Executor::detached_tasks()`][crate::Executor::detached_tasks] until it stops.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor};
+    ///
+    /// struct Sink;
+    ///
+    /// impl Actor for Sink {
+    ///     type Message = ();
+    ///
+    ///     async fn handle(&mut self, _: ()) {}
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Sink, 1);
+    /// assert_eq!(ex.detached_tasks(), [addr.id()]);
+    /// ```
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Sends a message, waiting while the mailbox is full.
+    ///
+    /// Returns the message back if the actor has stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor};
+    ///
+    /// struct Sink;
+    ///
+    /// impl Actor for Sink {
+    ///     type Message = u32;
+    ///
+    ///     async fn handle(&mut self, _: u32) {}
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Sink, 1);
+    ///
+    /// // The second message waits until the actor has received the first.
+    /// ex.block_on(async {
+    ///     addr.send(1).await.unwrap();
+    ///     addr.send(2).await.unwrap();
+    /// });
+    /// ```
+    pub async fn send(&self, message: A::Message) -> Result<(), SendError<A::Message>> {
+        self.mailbox.send(message).await
+    }
+
+    /// Sends a message if there is room in the mailbox.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor, TrySendError};
+    ///
+    /// struct Sink;
+    ///
+    /// impl Actor for Sink {
+    ///     type Message = u32;
+    ///
+    ///     async fn handle(&mut self, _: u32) {}
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Sink, 1);
+    ///
+    /// assert_eq!(addr.try_send(1), Ok(()));
+    /// assert_eq!(addr.try_send(2), Err(TrySendError::Full(2)));
+    /// ```
+    pub fn try_send(&self, message: A::Message) -> Result<(), TrySendError<A::Message>> {
+        self.mailbox.try_send(message)
+    }
+
+    /// Sends a message carrying a [`Reply`] and waits for the actor to answer.
+    ///
+    /// Returns `None` if the actor stopped or dropped the [`Reply`] without answering.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor, Reply};
+    ///
+    /// struct Doubler;
+    ///
+    /// impl Actor for Doubler {
+    ///     type Message = (u32, Reply<u32>);
+    ///
+    ///     async fn handle(&mut self, (n, reply): (u32, Reply<u32>)) {
+    ///         reply.send(n * 2);
+    ///     }
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Doubler, 16);
+    ///
+    /// let answer = ex.block_on(addr.ask(|reply| (21, reply)));
+    /// assert_eq!(answer, Some(42));
+    /// ```
+    pub async fn ask<T>(&self, message: impl FnOnce(Reply<T>) -> A::Message) -> Option<T> {
+        let slot = Arc::new(Mutex::new(ReplySlot {
+            value: None,
+            waker: None,
+            closed: false,
+        }));
+        let reply = Reply { slot: slot.clone() };
+        self.mailbox.send(message(reply)).await.ok()?;
+
+        future::poll_fn(|cx| {
+            let mut slot = slot.lock();
+            match slot.value.take() {
+                Some(value) => Poll::Ready(Some(value)),
+                None if slot.closed => Poll::Ready(None),
+                None => {
+                    slot.waker = Some(cx.waker().clone());
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Returns `true` if the actor has stopped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor};
+    ///
+    /// struct Sink;
+    ///
+    /// impl Actor for Sink {
+    ///     type Message = ();
+    ///
+    ///     async fn handle(&mut self, _: ()) {}
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Sink, 1);
+    /// assert!(!addr.is_stopped());
+    ///
+    /// drop(ex);
+    /// assert!(addr.is_stopped());
+    /// ```
+    pub fn is_stopped(&self) -> bool {
+        self.mailbox.state.lock().stopped
+    }
+
+    /// Returns statistics about the actor's mailbox.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor};
+    ///
+    /// struct Sink;
+    ///
+    /// impl Actor for Sink {
+    ///     type Message = ();
+    ///
+    ///     async fn handle(&mut self, _: ()) {}
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Sink, 16);
+    ///
+    /// addr.try_send(()).unwrap();
+    /// addr.try_send(()).unwrap();
+    /// assert_eq!(addr.metrics().len, 2);
+    ///
+    /// assert!(ex.try_tick());
+    /// let metrics = addr.metrics();
+    /// assert_eq!(metrics.len, 0);
+    /// assert_eq!(metrics.peak_len, 2);
+    /// assert_eq!(metrics.received, 2);
+    /// ```
+    pub fn metrics(&self) -> MailboxMetrics {
+        let state = self.mailbox.state.lock();
+        MailboxMetrics {
+            len: state.messages.len(),
+            capacity: self.mailbox.capacity,
+            peak_len: state.peak_len,
+            waiting_senders: state.senders.len(),
+            received: state.received,
+        }
+    }
+}
+
+impl<A: Actor> Clone for Addr<A> {
+    fn clone(&self) -> Addr<A> {
+        self.mailbox.state.lock().addrs += 1;
+        Addr {
+            mailbox: self.mailbox.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl<A: Actor> Drop for Addr<A> {
+    fn drop(&mut self) {
+        let mut state = self.mailbox.state.lock();
+        state.addrs -= 1;
+
+        // Let the actor finish the remaining messages and stop.
+        if state.addrs == 0 {
+            if let Some(waker) = state.receiver.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<A: Actor> fmt::Debug for Addr<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Addr")
+            .field("id", &self.id)
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+/// Statistics about the mailbox of an actor.
+///
+/// Returned by [`Addr::metrics()`].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Executor};
+///
+/// struct Sink;
+///
+/// impl Actor for Sink {
+///     type Message = ();
+///
+///     async fn handle(&mut self, _: ()) {}
+/// }
+///
+/// let ex = Executor::new();
+/// let addr = ex.spawn_actor(Sink, 16);
+///
+/// let metrics = addr.metrics();
+/// println!("{} of {} slots used", metrics.len, metrics.capacity);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MailboxMetrics {
+    /// Number of messages waiting to be handled.
+    pub len: usize,
+
+    /// Maximum number of messages the mailbox holds.
+    pub capacity: usize,
+
+    /// The highest number of messages that were waiting at once.
+    pub peak_len: usize,
+
+    /// Number of senders waiting for room in the mailbox.
+    pub waiting_senders: usize,
+
+    /// Number of messages the actor has received.
+    pub received: u64,
+}
+
+/// A one-time channel for answering a message sent with [`Addr::ask()`].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Reply};
+///
+/// struct Echo;
+///
+/// impl Actor for Echo {
+///     type Message = (String, Reply<String>);
+///
+///     async fn handle(&mut self, (text, reply): (String, Reply<String>)) {
+///         reply.send(text);
+///     }
+/// }
+/// ```
+pub struct Reply<T> {
+    slot: Arc<Mutex<ReplySlot<T>>>,
+}
+
+impl<T> Reply<T> {
+    /// Sends the answer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Reply};
+    ///
+    /// struct Answer;
+    ///
+    /// impl Actor for Answer {
+    ///     type Message = Reply<u32>;
+    ///
+    ///     async fn handle(&mut self, reply: Reply<u32>) {
+    ///         reply.send(42);
+    ///     }
+    /// }
+    /// ```
+    pub fn send(self, value: T) {
+        self.slot.lock().value = Some(value);
+    }
+}
+
+impl<T> Drop for Reply<T> {
+    fn drop(&mut self) {
+        let mut slot = self.slot.lock();
+        slot.closed = true;
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> fmt::Debug for Reply<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reply").finish_non_exhaustive()
+    }
+}
+
+/// The answer to a message sent with [`Addr::ask()`].
+struct ReplySlot<T> {
+    value: Option<T>,
+
+    /// The waker of the asking task.
+    waker: Option<Waker>,
+
+    /// Set when the [`Reply`] is dropped.
+    closed: bool,
+}
+
+/// An error returned by [`Addr::send()`] because the actor has stopped.
+///
+/// Contains the message that could not be sent.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Executor, SendError};
+///
+/// struct Sink;
+///
+/// impl Actor for Sink {
+///     type Message = u32;
+///
+///     async fn handle(&mut self, _: u32) {}
+/// }
+///
+/// let ex = Executor::new();
+/// let addr = ex.spawn_actor(Sink, 1);
+///
+/// drop(ex);
+/// assert_eq!(futures_lite::future::block_on(addr.send(1)), Err(SendError(1)));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SendError<M>(pub M);
+
+impl<M> fmt::Debug for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SendError(..)")
+    }
+}
+
+impl<M> fmt::Display for SendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("sending to a stopped actor")
+    }
+}
+
+impl<M> Error for SendError<M> {}
+
+/// An error returned by [`Addr::try_send()`].
+///
+/// Contains the message that could not be sent.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Actor, Executor, TrySendError};
+///
+/// struct Sink;
+///
+/// impl Actor for Sink {
+///     type Message = u32;
+///
+///     async fn handle(&mut self, _: u32) {}
+/// }
+///
+/// let ex = Executor::new();
+/// let addr = ex.spawn_actor(Sink, 1);
+///
+/// addr.try_send(1).unwrap();
+/// assert_eq!(addr.try_send(2), Err(TrySendError::Full(2)));
+///
+/// drop(ex);
+/// assert_eq!(addr.try_send(3), Err(TrySendError::Stopped(3)));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TrySendError<M> {
+    /// The mailbox is full.
+    Full(M),
+
+    /// The actor has stopped.
+    Stopped(M),
+}
+
+impl<M> fmt::Debug for TrySendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("Full(..)"),
+            TrySendError::Stopped(_) => f.write_str("Stopped(..)"),
+        }
+    }
+}
+
+impl<M> fmt::Display for TrySendError<M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrySendError::Full(_) => f.write_str("sending to a full mailbox"),
+            TrySendError::Stopped(_) => f.write_str("sending to a stopped actor"),
+        }
+    }
+}
+
+impl<M> Error for TrySendError<M> {}
+
+/// A bounded queue of messages for an actor.
+#[derive(Debug)]
+pub(crate) struct Mailbox<M> {
+    state: Mutex<MailboxState<M>>,
+    capacity: usize,
+}
+
+/// The state of a mailbox.
+#[derive(Debug)]
+struct MailboxState<M> {
+    messages: VecDeque<M>,
+
+    /// Wakers of senders waiting for room, or `None` once notified.
+    senders: Slab<Option<Waker>>,
+
+    /// The waker of the actor waiting for a message.
+    receiver: Option<Waker>,
+
+    /// Number of addresses.
+    addrs: usize,
+
+    /// Set once the actor stops, after which messages are rejected.
+    stopped: bool,
+
+    peak_len: usize,
+    received: u64,
+}
+
+impl<M> MailboxState<M> {
+    /// Wakes a sender waiting for room.
+    fn notify_sender(&mut self) {
+        if let Some(waker) = self.senders.iter_mut().find_map(|(_, w)| w.take()) {
+            waker.wake();
+        }
+    }
+
+    /// Queues a message and wakes the actor.
+    fn push(&mut self, message: M) {
+        self.messages.push_back(message);
+        self.peak_len = self.peak_len.max(self.messages.len());
+        if let Some(waker) = self.receiver.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<M> Mailbox<M> {
+    /// Creates a mailbox with room for `capacity` messages and one address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub(crate) fn new(capacity: usize) -> Mailbox<M> {
+        assert!(capacity > 0, "the capacity of a mailbox must not be zero");
+        Mailbox {
+            state: Mutex::new(MailboxState {
+                messages: VecDeque::new(),
+                senders: Slab::new(),
+                receiver: None,
+                addrs: 1,
+                stopped: false,
+                peak_len: 0,
+                received: 0,
+            }),
+            capacity,
+        }
+    }
+
+    /// Queues a message if there is room.
+    fn try_send(&self, message: M) -> Result<(), TrySendError<M>> {
+        let mut state = self.state.lock();
+        if state.stopped {
+            Err(TrySendError::Stopped(message))
+        } else if state.messages.len() < self.capacity {
+            state.push(message);
+            Ok(())
+        } else {
+            Err(TrySendError::Full(message))
+        }
+    }
+
+    /// Queues a message, waiting for room.
+    async fn send(&self, message: M) -> Result<(), SendError<M>> {
+        let mut message = Some(message);
+        let mut waiting = Waiting {
+            mailbox: self,
+            key: None,
+        };
+
+        future::poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.stopped {
+                return Poll::Ready(Err(SendError(message.take().unwrap())));
+            }
+            if state.messages.len() < self.capacity {
+                if let Some(key) = waiting.key.take() {
+                    state.senders.remove(key);
+                }
+                state.push(message.take().unwrap());
+                return Poll::Ready(Ok(()));
+            }
+
+            match waiting.key {
+                Some(key) => state.senders[key] = Some(cx.waker().clone()),
+                None => waiting.key = Some(state.senders.insert(Some(cx.waker().clone()))),
+            }
+            Poll::Pending
+        })
+        .await
+    }
+
+    /// Takes the next message, waiting for one.
+    ///
+    /// Returns `None` once all addresses are dropped and the mailbox is empty.
+    async fn recv(&self) -> Option<M> {
+        future::poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if let Some(message) = state.messages.pop_front() {
+                state.received += 1;
+                state.notify_sender();
+                Poll::Ready(Some(message))
+            } else if state.addrs == 0 {
+                Poll::Ready(None)
+            } else {
+                state.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    /// Rejects new messages and drops queued ones.
+    fn stop(&self) {
+        let messages = {
+            let mut state = self.state.lock();
+            state.stopped = true;
+            for (_, waker) in state.senders.iter_mut() {
+                if let Some(waker) = waker.take() {
+                    waker.wake();
+                }
+            }
+            std::mem::take(&mut state.messages)
+        };
+
+        // Dropping a message may drop a `Reply`, which wakes its asking task.
+        drop(messages);
+    }
+}
+
+/// A sender waiting for room in a mailbox.
+struct Waiting<'m, M> {
+    mailbox: &'m Mailbox<M>,
+    key: Option<usize>,
+}
+
+impl<M> Drop for Waiting<'_, M> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            let mut state = self.mailbox.state.lock();
+
+            // Pass on a notification this sender didn't use.
+            if state.senders.remove(key).is_none() {
+                state.notify_sender();
+            }
+        }
+    }
+}
+
+/// Runs an actor until all of its addresses are dropped and its mailbox is empty.
+///
+/// The mailbox stops accepting messages once the returned future completes or is dropped.
+pub(crate) fn run<A: Actor>(
+    mut actor: A,
+    mailbox: Arc<Mailbox<A::Message>>,
+) -> impl Future<Output = Result<(), Infallible>> + Send {
+    // The guard lives outside the future so that it also runs if the task gets cancelled before
+    // its first poll.
+    let guard = {
+        let mailbox = mailbox.clone();
+        CallOnDrop(move || mailbox.stop())
+    };
+
+    async move {
+        let _guard = guard;
+        while let Some(message) = mailbox.recv().await {
+            actor.handle(message).await;
+        }
+        actor.stopped().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+//! 64-bit counters that also work on targets without 64-bit atomics.
+
+#[cfg(target_has_atomic = "64")]
+pub(crate) use std::sync::atomic::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+pub(crate) use fallback::AtomicU64;
+
+#[cfg(not(target_has_atomic = "64"))]
+mod fallback {
+    use std::sync::atomic::Ordering;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// A `u64` behind a lock, with the part of the `AtomicU64` API this crate uses.
+    ///
+    /// Orderings are ignored, since the lock makes every operation sequentially consistent.
+    #[derive(Debug, Default)]
+    pub(crate) struct AtomicU64 {
+        value: Mutex<u64>,
+    }
+
+    impl AtomicU64 {
+        pub(crate) const fn new(value: u64) -> AtomicU64 {
+            AtomicU64 {
+                value: Mutex::new(value),
+            }
+        }
+
+        fn lock(&self) -> MutexGuard<'_, u64> {
+            // Nothing can panic while the lock is held.
+            self.value.lock().unwrap_or_else(|e| e.into_inner())
+        }
+
+        pub(crate) fn load(&self, _: Ordering) -> u64 {
+            *self.lock()
+        }
+
+        pub(crate) fn store(&self, value: u64, _: Ordering) {
+            *self.lock() = value;
+        }
+
+        pub(crate) fn fetch_add(&self, value: u64, _: Ordering) -> u64 {
+            let mut current = self.lock();
+            let previous = *current;
+            *current = previous.wrapping_add(value);
+            previous
+        }
+
+        pub(crate) fn fetch_max(&self, value: u64, _: Ordering) -> u64 {
+            let mut current = self.lock();
+            let previous = *current;
+            *current = previous.max(value);
+            previous
+        }
+    }
+}
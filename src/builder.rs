@@ -0,0 +1,449 @@
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::taskqueue::{GlobalQueue, TaskQueue};
+use crate::timer::SleepHook;
+use crate::{Executor, QueueKind, TaskFailure, TaskTimes};
+
+/// Configures and creates an [`Executor`].
+///
+/// All methods except those setting hooks are `const`, so a configured executor can be stored in
+/// a `static`.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Builder, Executor};
+///
+/// static EX: Executor<'_> = Builder::new().track_tasks(false).build();
+///
+/// let task = EX.spawn(async { 1 + 2 });
+/// ```
+#[derive(Debug, Clone)]
+pub struct Builder {
+    pub(crate) config: Config,
+}
+
+impl Builder {
+    /// Creates a builder with the default configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().build();
+    /// ```
+    pub const fn new() -> Builder {
+        Builder {
+            config: Config::new(),
+        }
+    }
+
+    /// Sets whether the executor keeps track of every unfinished task.
+    ///
+    /// Tracking is enabled by default. It is what allows dropping the executor to cancel all of
+    /// its unfinished tasks.
+    ///
+    /// Without tracking, the executor only counts unfinished tasks, which saves registering a
+    /// waker on every spawn. Dropping such an executor cancels tasks that are queued or waiting
+    /// for a slot at that moment and leaks all other tasks, as if by [`std::mem::forget`]: their
+    /// futures are never polled nor dropped again. If any task is left, the executor's internal
+    /// state is leaked along with it, and whenever a leaked task gets woken, its runnable is pushed
+    /// onto one of the leaked queues and stays there. This is meant for executors that live for the
+    /// whole program, such as those stored in a `static`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().track_tasks(false).build();
+    ///
+    /// let task = ex.spawn(async {});
+    /// assert!(!ex.is_empty());
+    ///
+    /// assert!(ex.try_tick());
+    /// assert!(ex.is_empty());
+    /// ```
+    pub const fn track_tasks(mut self, track: bool) -> Builder {
+        self.config.track_tasks = track;
+        self
+    }
+
+    /// Sets whether the executor measures the time spent polling each task.
+    ///
+    /// Timing is disabled by default. When enabled, every poll of a task is timed, both in
+    /// wall-clock time and, on Linux, in CPU time of the polling thread. The totals of unfinished
+    /// tasks are returned by [`Executor::task_times()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().time_tasks(true).build();
+    ///
+    /// let task = ex.spawn(async {});
+    /// assert_eq!(ex.task_times().len(), 1);
+    /// ```
+    pub const fn time_tasks(mut self, time: bool) -> Builder {
+        self.config.time_tasks = time;
+        self
+    }
+
+    /// Sets a function to call with the times of every task that completes.
+    ///
+    /// The function is called on the thread that polled the task to completion, right after the
+    /// last poll. Setting it enables [timing][Builder::time_tasks()]. Tasks that get cancelled
+    /// are not reported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// use async_executor::Builder;
+    ///
+    /// let slowest = Arc::new(Mutex::new(None));
+    /// let ex = Builder::new()
+    ///     .on_task_complete({
+    ///         let slowest = slowest.clone();
+    ///         Arc::new(move |times| {
+    ///             let mut slowest = slowest.lock().unwrap();
+    ///             *slowest = Some(times.wall_time).max(*slowest);
+    ///         })
+    ///     })
+    ///     .build();
+    ///
+    /// ex.block_on(ex.spawn(async {}));
+    /// assert!(slowest.lock().unwrap().is_some());
+    /// ```
+    pub fn on_task_complete(mut self, hook: Arc<dyn Fn(&TaskTimes) + Send + Sync>) -> Builder {
+        self.config.time_tasks = true;
+        self.config.on_task_complete = Some(Hook(hook));
+        self
+    }
+
+    /// Sets a function to call with every detached task that returns an error or panics.
+    ///
+    /// The function is called on the thread that polled the task to completion. Without it,
+    /// failures of tasks spawned with [`Executor::spawn_detached()`] are only reported as
+    /// `tracing` events if the `tracing` feature is enabled, and panics are left to the panic
+    /// hook.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// use async_executor::Builder;
+    ///
+    /// let (s, r) = std::sync::mpsc::channel();
+    /// let s = std::sync::Mutex::new(s);
+    /// let ex = Builder::new()
+    ///     .on_task_failure(Arc::new(move |failure| {
+    ///         s.lock().unwrap().send(failure.id).unwrap();
+    ///     }))
+    ///     .build();
+    ///
+    /// let id = ex.spawn_detached(async { Err("oops") });
+    /// assert!(ex.try_tick());
+    /// assert_eq!(r.try_recv(), Ok(id));
+    /// ```
+    pub fn on_task_failure(mut self, hook: Arc<dyn Fn(&TaskFailure) + Send + Sync>) -> Builder {
+        self.config.on_task_failure = Some(Hook(hook));
+        self
+    }
+
+    /// Sets whether the executor counts how often each task gets woken.
+    ///
+    /// Tracking is disabled by default. When enabled, tasks are polled with a waker that counts
+    /// wakes before waking the task. The counters of unfinished tasks are returned by
+    /// [`Executor::task_wakes()`], and tasks that have waited for a wake for a long time by
+    /// [`Executor::suspected_lost_wakeups()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().track_wakes(true).build();
+    /// ```
+    pub const fn track_wakes(mut self, track: bool) -> Builder {
+        self.config.track_wakes = track;
+        self
+    }
+
+    /// Sets whether the executor records how long tasks wait to be polled.
+    ///
+    /// Recording is disabled by default. When enabled, the time between a task getting scheduled
+    /// and getting polled is recorded into [`Executor::latency_histogram()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().record_latency(true).build();
+    /// ```
+    pub const fn record_latency(mut self, record: bool) -> Builder {
+        self.config.record_latency = record;
+        self
+    }
+
+    /// Sets whether every runner also records how long the tasks it polls waited.
+    ///
+    /// Enabling this enables [recording latency][Builder::record_latency()] as well. The
+    /// histograms of current runners are returned by [`Executor::runner_latency_histograms()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().record_runner_latency(true).build();
+    /// ```
+    pub const fn record_runner_latency(mut self, record: bool) -> Builder {
+        self.config.record_runner_latency = record;
+        self.config.record_latency |= record;
+        self
+    }
+
+    /// Sets how long tasks get to wind down after their cancellation token fires.
+    ///
+    /// Once the grace period has passed, tasks spawned with [`Executor::spawn_with_token()`] or
+    /// [`Executor::spawn_with_timeout()`] that are still running get dropped, and so do the
+    /// tasks they spawned. The default is zero, meaning they get dropped right away.
+    ///
+    /// A non-zero grace period is timed by the executor's [timer](crate#timers).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new()
+    ///     .cancel_grace_period(Duration::from_millis(100))
+    ///     .build();
+    /// ```
+    pub const fn cancel_grace_period(mut self, grace: Duration) -> Builder {
+        self.config.cancel_grace_period = grace;
+        self
+    }
+
+    /// Sets the function the executor uses to sleep until a deadline.
+    ///
+    /// The function is called with the deadline and returns a future that completes at that
+    /// point, whose output is ignored. It times timeouts, grace periods and supervisor backoff in
+    /// place of the [timer thread](crate#timers), so that they can run on the timer of another
+    /// runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::{Builder, Cancelled};
+    /// use async_io::Timer;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().sleep(Timer::at).build();
+    ///
+    /// let task = ex.spawn_with_timeout(Duration::from_millis(10), future::pending::<()>());
+    /// assert_eq!(ex.block_on(task), Err(Cancelled));
+    /// ```
+    pub fn sleep<F, S>(mut self, hook: F) -> Builder
+    where
+        F: Fn(Instant) -> S + Send + Sync + 'static,
+        S: Future + Send + 'static,
+    {
+        self.config.sleep = Some(SleepHook(Arc::new(move |deadline| {
+            let sleep = hook(deadline);
+            Box::pin(async move {
+                sleep.await;
+            })
+        })));
+        self
+    }
+
+    /// Sets the kind of queues the executor keeps runnable tasks in.
+    ///
+    /// The default is [`QueueKind::Crossbeam`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Builder, QueueKind};
+    ///
+    /// let ex = Builder::new().queue(QueueKind::Priority).build();
+    /// ```
+    pub const fn queue(mut self, kind: QueueKind) -> Builder {
+        self.config.queue = kind;
+        self.config.custom_queue = None;
+        self
+    }
+
+    /// Makes the executor keep runnable tasks in queues of a custom type.
+    ///
+    /// The global queue is created with `Q::default()` when the executor is first used. Tasks get
+    /// the same keys they would get in queues of the given `keys` kind: priorities with
+    /// [`QueueKind::Priority`], deadlines with [`QueueKind::Deadline`], and the same key for every
+    /// task otherwise. See [`TaskQueue`] for an example.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use std::collections::VecDeque;
+    /// # use std::sync::Mutex;
+    /// # use async_executor::{LocalTaskQueue, Runnable, TaskQueue};
+    /// # #[derive(Default)]
+    /// # struct Fifo(Mutex<VecDeque<Runnable>>);
+    /// # impl TaskQueue for Fifo {
+    /// #     type Local = Local;
+    /// #     fn push(&self, task: Runnable, _key: u64) { self.0.lock().unwrap().push_back(task) }
+    /// #     fn pop(&self) -> Option<Runnable> { self.0.lock().unwrap().pop_front() }
+    /// #     fn local(&self) -> Local { Local(VecDeque::new()) }
+    /// # }
+    /// # struct Local(VecDeque<Runnable>);
+    /// # impl LocalTaskQueue for Local {
+    /// #     type Global = Fifo;
+    /// #     type Handle = ();
+    /// #     fn push(&mut self, task: Runnable, _key: u64) -> Result<(), Runnable> {
+    /// #         self.0.push_back(task);
+    /// #         Ok(())
+    /// #     }
+    /// #     fn pop(&mut self) -> Option<Runnable> { self.0.pop_front() }
+    /// #     fn is_empty(&self) -> bool { self.0.is_empty() }
+    /// #     fn append(&mut self, other: &mut Local, _global: &Fifo) -> bool {
+    /// #         self.0.append(&mut other.0);
+    /// #         false
+    /// #     }
+    /// #     fn spill(&mut self, global: &Fifo) { self.flush(global) }
+    /// #     fn flush(&mut self, global: &Fifo) { global.0.lock().unwrap().extend(self.0.drain(..)) }
+    /// #     fn steal_global(&mut self, global: &Fifo) { self.0.extend(global.pop()) }
+    /// #     fn steal_local(&mut self, _other: &()) {}
+    /// #     fn handle(&self) {}
+    /// # }
+    /// use async_executor::{Builder, QueueKind};
+    ///
+    /// // `Fifo` implements `TaskQueue`.
+    /// let ex = Builder::new()
+    ///     .custom_queue::<Fifo>(QueueKind::Crossbeam)
+    ///     .build();
+    /// ```
+    ///
+    /// [`TaskQueue`]: crate::TaskQueue
+    pub const fn custom_queue<Q: TaskQueue + Default>(mut self, keys: QueueKind) -> Builder {
+        self.config.queue = keys;
+        self.config.custom_queue = Some(GlobalQueue::custom::<Q>);
+        self
+    }
+
+    /// Creates an executor with this configuration.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Builder, Executor};
+    ///
+    /// let ex: Executor<'_> = Builder::new().build();
+    /// ```
+    pub const fn build<'a>(self) -> Executor<'a> {
+        Executor {
+            state: once_cell::sync::OnceCell::new(),
+            builder: self,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Builder {
+        Builder::new()
+    }
+}
+
+/// Executor configuration.
+#[derive(Debug, Clone)]
+pub(crate) struct Config {
+    /// Whether unfinished tasks are registered so that dropping the executor cancels them.
+    pub(crate) track_tasks: bool,
+
+    /// The kind of task queues, or the kind whose keys a custom queue gets.
+    pub(crate) queue: QueueKind,
+
+    /// Creates the global queue if it is of a custom type.
+    pub(crate) custom_queue: Option<fn() -> GlobalQueue>,
+
+    /// Whether polls of tasks are timed.
+    pub(crate) time_tasks: bool,
+
+    /// Called with the times of every completed task.
+    pub(crate) on_task_complete: Option<Hook<TaskTimes>>,
+
+    /// Called with every detached task that fails.
+    pub(crate) on_task_failure: Option<Hook<TaskFailure>>,
+
+    /// Whether wakes of tasks are counted.
+    pub(crate) track_wakes: bool,
+
+    /// How long cancelled tasks get to wind down before they are dropped.
+    pub(crate) cancel_grace_period: Duration,
+
+    /// Sleeps until a deadline instead of the timer thread.
+    pub(crate) sleep: Option<SleepHook>,
+
+    /// Whether scheduling latency is recorded.
+    pub(crate) record_latency: bool,
+
+    /// Whether runners record their own scheduling latency.
+    pub(crate) record_runner_latency: bool,
+}
+
+impl Config {
+    /// Returns the default configuration.
+    pub(crate) const fn new() -> Config {
+        Config {
+            track_tasks: true,
+            queue: QueueKind::Crossbeam,
+            custom_queue: None,
+            time_tasks: false,
+            on_task_complete: None,
+            on_task_failure: None,
+            track_wakes: false,
+            cancel_grace_period: Duration::ZERO,
+            sleep: None,
+            record_latency: false,
+            record_runner_latency: false,
+        }
+    }
+}
+
+/// A function set on the builder to be called on some event.
+pub(crate) struct Hook<T>(Arc<dyn Fn(&T) + Send + Sync>);
+
+impl<T> Hook<T> {
+    /// Calls the function.
+    pub(crate) fn call(&self, arg: &T) {
+        (self.0)(arg)
+    }
+}
+
+// Not derived, since that would require `T: Clone`.
+impl<T> Clone for Hook<T> {
+    fn clone(&self) -> Hook<T> {
+        Hook(self.0.clone())
+    }
+}
+
+impl<T> fmt::Debug for Hook<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hook").finish_non_exhaustive()
+    }
+}
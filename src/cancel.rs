@@ -0,0 +1,350 @@
+use std::cell::{Cell, RefCell};
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll, Waker};
+
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::CallOnDrop;
+
+thread_local! {
+    /// The token of the task being polled on this thread.
+    static CURRENT: RefCell<Option<CancellationToken>> = const { RefCell::new(None) };
+
+    /// Set while `CURRENT` holds a token, so that spawning can check for one cheaply.
+    static ENTERED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Returns a child token of the current task's token for a task it spawns, if it has a token.
+pub(crate) fn inherited() -> Option<CancellationToken> {
+    if !ENTERED.with(Cell::get) {
+        return None;
+    }
+    CURRENT.with(|current| {
+        current
+            .borrow()
+            .as_ref()
+            .map(CancellationToken::child_token)
+    })
+}
+
+/// Makes no token the current one until the returned guard is dropped.
+pub(crate) fn exit() -> CallOnDrop<impl Fn()> {
+    let previous = CURRENT.with(|current| current.replace(None));
+    let entered = ENTERED.with(|flag| flag.replace(false));
+    let previous = RefCell::new(previous);
+    CallOnDrop(move || {
+        CURRENT.with(|current| current.swap(&previous));
+        ENTERED.with(|flag| flag.set(entered));
+    })
+}
+
+/// Signals tasks that they should stop.
+///
+/// Cancellation is cooperative: tasks check [`CancellationToken::is_cancelled()`] or await
+/// [`CancellationToken::cancelled()`] and wind down. Tasks spawned with
+/// [`Executor::spawn_with_token()`][crate::Executor::spawn_with_token] are additionally dropped
+/// by the executor once the token fires and the
+/// [grace period][crate::Builder::cancel_grace_period] has passed.
+///
+/// A task's token is returned by [`CancellationToken::current()`] while it runs. Tasks it spawns
+/// get a [child token][CancellationToken::child_token()] of it, and are dropped just like it
+/// once the token fires and the grace period has passed. A dropped task never completes, so
+/// awaiting its [`Task`][crate::Task] panics; use [`Task::fallible()`][crate::Task::fallible]
+/// to get `None` instead. Tasks spawned with a token of their own don't inherit one.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{CancellationToken, Executor};
+///
+/// let ex = Executor::new();
+/// let token = CancellationToken::new();
+///
+/// let task = ex.spawn_with_token(token.clone(), async {
+///     let token = CancellationToken::current().unwrap();
+///     token.cancelled().await;
+///     "stopped"
+/// });
+///
+/// token.cancel();
+/// assert_eq!(ex.block_on(task), Ok("stopped"));
+/// ```
+#[derive(Clone)]
+pub struct CancellationToken {
+    inner: Arc<Inner>,
+}
+
+impl CancellationToken {
+    /// Creates a token that is not cancelled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::CancellationToken;
+    ///
+    /// let token = CancellationToken::new();
+    /// assert!(!token.is_cancelled());
+    /// ```
+    pub fn new() -> CancellationToken {
+        CancellationToken {
+            inner: Arc::new(Inner {
+                state: Mutex::new(TokenState::default()),
+                parent: None,
+            }),
+        }
+    }
+
+    /// Returns the token of the currently running task, if it has one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{CancellationToken, Executor};
+    ///
+    /// let ex = Executor::new();
+    /// assert!(CancellationToken::current().is_none());
+    ///
+    /// let task = ex.spawn_with_token(CancellationToken::new(), async {
+    ///     CancellationToken::current().is_some()
+    /// });
+    /// assert_eq!(ex.block_on(task), Ok(true));
+    /// ```
+    pub fn current() -> Option<CancellationToken> {
+        CURRENT.with(|current| current.borrow().clone())
+    }
+
+    /// Creates a token that gets cancelled together with this one.
+    ///
+    /// Cancelling the child token doesn't cancel this one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::CancellationToken;
+    ///
+    /// let parent = CancellationToken::new();
+    /// let child = parent.child_token();
+    ///
+    /// child.cancel();
+    /// assert!(!parent.is_cancelled());
+    ///
+    /// let child = parent.child_token();
+    /// parent.cancel();
+    /// assert!(child.is_cancelled());
+    /// ```
+    pub fn child_token(&self) -> CancellationToken {
+        let mut state = self.inner.state.lock();
+        let cancelled = state.cancelled;
+        let entry = state.children.vacant_entry();
+        let child = Arc::new(Inner {
+            state: Mutex::new(TokenState {
+                cancelled,
+                ..TokenState::default()
+            }),
+            parent: Some((self.inner.clone(), entry.key())),
+        });
+
+        // A cancelled token doesn't need to know its children.
+        if !cancelled {
+            entry.insert(Arc::downgrade(&child));
+        }
+        CancellationToken { inner: child }
+    }
+
+    /// Cancels the token and all of its child tokens.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::CancellationToken;
+    ///
+    /// let token = CancellationToken::new();
+    /// token.cancel();
+    /// assert!(token.is_cancelled());
+    /// ```
+    pub fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    /// Returns `true` if the token was cancelled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::CancellationToken;
+    ///
+    /// let token = CancellationToken::new();
+    /// assert!(!token.is_cancelled());
+    /// ```
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.state.lock().cancelled
+    }
+
+    /// Waits until the token is cancelled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::CancellationToken;
+    /// use futures_lite::future;
+    ///
+    /// let token = CancellationToken::new();
+    /// token.cancel();
+    /// future::block_on(token.cancelled());
+    /// ```
+    pub fn cancelled(&self) -> impl Future<Output = ()> + Send + '_ {
+        Cancellation {
+            token: self,
+            key: None,
+        }
+    }
+
+    /// Makes this the current token until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> CallOnDrop<impl Fn()> {
+        let previous = CURRENT.with(|current| current.replace(Some(self.clone())));
+        let entered = ENTERED.with(|flag| flag.replace(true));
+        let previous = RefCell::new(previous);
+        CallOnDrop(move || {
+            CURRENT.with(|current| current.swap(&previous));
+            ENTERED.with(|flag| flag.set(entered));
+        })
+    }
+}
+
+impl Default for CancellationToken {
+    fn default() -> CancellationToken {
+        CancellationToken::new()
+    }
+}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// A future that waits for a token to be cancelled.
+struct Cancellation<'t> {
+    token: &'t CancellationToken,
+
+    /// The key of the registered waker.
+    key: Option<usize>,
+}
+
+impl Future for Cancellation<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.token.inner.state.lock();
+        if state.cancelled {
+            return Poll::Ready(());
+        }
+        match self.key {
+            Some(key) => state.wakers[key] = cx.waker().clone(),
+            None => {
+                let key = state.wakers.insert(cx.waker().clone());
+                drop(state);
+                self.key = Some(key);
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Cancellation<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            self.token.inner.state.lock().wakers.try_remove(key);
+        }
+    }
+}
+
+/// The state shared by clones of a token.
+struct Inner {
+    state: Mutex<TokenState>,
+
+    /// The parent token and this token's key in its list of children.
+    parent: Option<(Arc<Inner>, usize)>,
+}
+
+impl Inner {
+    /// Cancels the token and its children.
+    fn cancel(&self) {
+        let (wakers, children) = {
+            let mut state = self.state.lock();
+            if state.cancelled {
+                return;
+            }
+            state.cancelled = true;
+
+            // No wakers or children are registered after cancellation, so the keys held by
+            // those being removed don't get reused.
+            (
+                std::mem::take(&mut state.wakers),
+                std::mem::take(&mut state.children),
+            )
+        };
+
+        for (_, waker) in wakers {
+            waker.wake();
+        }
+        for (_, child) in children {
+            if let Some(child) = child.upgrade() {
+                child.cancel();
+            }
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some((parent, key)) = &self.parent {
+            parent.state.lock().children.try_remove(*key);
+        }
+    }
+}
+
+/// The state of a token.
+#[derive(Default)]
+struct TokenState {
+    cancelled: bool,
+
+    /// Wakers of tasks waiting for cancellation.
+    wakers: Slab<Waker>,
+
+    /// Child tokens.
+    children: Slab<Weak<Inner>>,
+}
+
+/// The error of a task that was dropped after its [`CancellationToken`] fired.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// use async_executor::{Cancelled, Executor};
+/// use futures_lite::future;
+///
+/// let ex = Executor::new();
+///
+/// let task = ex.spawn_with_timeout(Duration::from_millis(10), future::pending::<()>());
+/// assert_eq!(ex.block_on(task), Err(Cancelled));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("task was cancelled")
+    }
+}
+
+impl Error for Cancelled {}
@@ -0,0 +1,105 @@
+//! Waker events in the format of `console-subscriber`, enabled by the `console` feature.
+//!
+//! Tools like `tokio-console` recognize tasks by their `runtime.spawn` span and count polls by
+//! entering that span. Everything a task does with its waker is reported as a `runtime::waker`
+//! event that refers to the task's span ID.
+
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+use std::task::{RawWaker, RawWakerVTable, Waker};
+
+use tracing::Span;
+
+/// Wakers handed to an instrumented task.
+#[derive(Debug)]
+pub(crate) struct TaskWakers {
+    /// The waker shared by the task's wakers, if the task's span is enabled.
+    shared: Option<Arc<Inner>>,
+}
+
+impl TaskWakers {
+    /// Creates wakers that report events for the task with the given span.
+    pub(crate) fn new(span: &Span) -> TaskWakers {
+        TaskWakers {
+            shared: span.id().map(|id| {
+                Arc::new(Inner {
+                    id: id.into_u64(),
+                    waker: None,
+                })
+            }),
+        }
+    }
+
+    /// Returns a waker that reports events and wakes `waker`, or `None` if not reporting.
+    ///
+    /// The returned waker is borrowed from `self` and must not be dropped.
+    pub(crate) fn get(&mut self, waker: &Waker) -> Option<ManuallyDrop<Waker>> {
+        let shared = self.shared.as_mut()?;
+
+        // Replace a stale waker. Clones that are still alive keep waking the old one.
+        let current = shared.waker.as_ref().is_some_and(|w| w.will_wake(waker));
+        if !current {
+            match Arc::get_mut(shared) {
+                Some(inner) => inner.waker = Some(waker.clone()),
+                None => {
+                    *shared = Arc::new(Inner {
+                        id: shared.id,
+                        waker: Some(waker.clone()),
+                    })
+                }
+            }
+        }
+
+        let raw = RawWaker::new(Arc::as_ptr(shared).cast(), &VTABLE);
+        Some(ManuallyDrop::new(unsafe { Waker::from_raw(raw) }))
+    }
+}
+
+/// The state behind an instrumented waker.
+#[derive(Debug)]
+struct Inner {
+    /// The span ID of the task.
+    id: u64,
+
+    /// The waker of the task.
+    waker: Option<Waker>,
+}
+
+impl Inner {
+    /// Reports an operation on a waker.
+    fn report(&self, op: &'static str) {
+        tracing::trace!(target: "runtime::waker", op, task.id = self.id);
+    }
+
+    /// Wakes the task.
+    fn wake(&self) {
+        if let Some(waker) = &self.waker {
+            waker.wake_by_ref();
+        }
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+unsafe fn clone(ptr: *const ()) -> RawWaker {
+    let inner = ManuallyDrop::new(Arc::from_raw(ptr.cast::<Inner>()));
+    inner.report("waker.clone");
+    RawWaker::new(Arc::into_raw(Arc::clone(&inner)).cast(), &VTABLE)
+}
+
+unsafe fn wake(ptr: *const ()) {
+    let inner = Arc::from_raw(ptr.cast::<Inner>());
+    inner.report("waker.wake");
+    inner.wake();
+}
+
+unsafe fn wake_by_ref(ptr: *const ()) {
+    let inner = &*ptr.cast::<Inner>();
+    inner.report("waker.wake_by_ref");
+    inner.wake();
+}
+
+unsafe fn drop(ptr: *const ()) {
+    let inner = Arc::from_raw(ptr.cast::<Inner>());
+    inner.report("waker.drop");
+}
@@ -0,0 +1,80 @@
+use std::convert::TryFrom;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+use crate::atomic::AtomicU64;
+
+/// Statistics about tasks spawned with a deadline.
+///
+/// A task meets its deadline if it completes before the deadline passes. Cancelled tasks are
+/// not counted.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::{Duration, Instant};
+///
+/// use async_executor::Executor;
+///
+/// let ex = Executor::new();
+///
+/// let deadline = Instant::now() + Duration::from_secs(60);
+/// let task = ex.spawn_with_deadline(deadline, async {});
+/// assert!(ex.try_tick());
+///
+/// let metrics = ex.deadline_metrics();
+/// assert_eq!(metrics.met, 1);
+/// assert_eq!(metrics.missed, 0);
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DeadlineMetrics {
+    /// Number of tasks that completed before their deadline.
+    pub met: u64,
+
+    /// Number of tasks that completed after their deadline.
+    pub missed: u64,
+
+    /// Sum of how late the tasks that missed their deadline completed.
+    pub total_lateness: Duration,
+
+    /// How late the latest task completed.
+    pub max_lateness: Duration,
+}
+
+/// Counters behind [`DeadlineMetrics`].
+#[derive(Debug, Default)]
+pub(crate) struct DeadlineCounters {
+    met: AtomicU64,
+    missed: AtomicU64,
+
+    /// Total lateness in nanoseconds.
+    total_lateness: AtomicU64,
+
+    /// Maximum lateness in nanoseconds.
+    max_lateness: AtomicU64,
+}
+
+impl DeadlineCounters {
+    /// Records that a task with the given deadline completed just now.
+    pub(crate) fn record(&self, deadline: Instant) {
+        let lateness = Instant::now().saturating_duration_since(deadline);
+        if lateness == Duration::ZERO {
+            self.met.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let nanos = u64::try_from(lateness.as_nanos()).unwrap_or(u64::MAX);
+            self.missed.fetch_add(1, Ordering::Relaxed);
+            self.total_lateness.fetch_add(nanos, Ordering::Relaxed);
+            self.max_lateness.fetch_max(nanos, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns a snapshot of the counters.
+    pub(crate) fn metrics(&self) -> DeadlineMetrics {
+        DeadlineMetrics {
+            met: self.met.load(Ordering::Relaxed),
+            missed: self.missed.load(Ordering::Relaxed),
+            total_lateness: Duration::from_nanos(self.total_lateness.load(Ordering::Relaxed)),
+            max_lateness: Duration::from_nanos(self.max_lateness.load(Ordering::Relaxed)),
+        }
+    }
+}
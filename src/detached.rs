@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::error::Error;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::Ordering;
+
+use futures_lite::FutureExt;
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::atomic::AtomicU64;
+use crate::builder::Hook;
+
+/// A detached task that returned an error or panicked.
+///
+/// Passed to the function set with
+/// [`Builder::on_task_failure()`][crate::Builder::on_task_failure].
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+///
+/// use async_executor::{Builder, TaskFailure};
+///
+/// fn report(failure: &TaskFailure) {
+///     let what = if failure.panicked { "panicked" } else { "failed" };
+///     eprintln!("task {} {}: {}", failure.id, what, failure.error);
+/// }
+///
+/// let ex = Builder::new().on_task_failure(Arc::new(report)).build();
+/// ex.spawn_detached(async { Err("oops") });
+/// assert!(ex.try_tick());
+/// ```
+#[derive(Debug)]
+pub struct TaskFailure {
+    /// The ID of the task, unique within its executor.
+    pub id: u64,
+
+    /// The error the task returned, or the message it panicked with.
+    pub error: Box<dyn Error + Send + Sync>,
+
+    /// Whether the task panicked.
+    pub panicked: bool,
+}
+
+impl TaskFailure {
+    /// Creates a failure from the payload of a panic.
+    pub(crate) fn panic(id: u64, payload: Box<dyn Any + Send>) -> TaskFailure {
+        let message = match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => match payload.downcast::<&'static str>() {
+                Ok(message) => message.to_string(),
+                Err(_) => "Box<dyn Any>".to_string(),
+            },
+        };
+        TaskFailure {
+            id,
+            error: message.into(),
+            panicked: true,
+        }
+    }
+}
+
+/// Runs the future of the task with the given ID, turning errors and panics into failures.
+pub(crate) async fn catch_failure<E>(
+    id: u64,
+    future: impl Future<Output = Result<(), E>>,
+) -> Result<(), TaskFailure>
+where
+    E: Into<Box<dyn Error + Send + Sync>>,
+{
+    match AssertUnwindSafe(future).catch_unwind().await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(error)) => Err(TaskFailure {
+            id,
+            error: error.into(),
+            panicked: false,
+        }),
+        Err(payload) => Err(TaskFailure::panic(id, payload)),
+    }
+}
+
+/// Unfinished detached tasks of an executor.
+#[derive(Debug)]
+pub(crate) struct Detached {
+    /// IDs of unfinished tasks.
+    live: Mutex<Slab<u64>>,
+
+    /// Number of tasks that failed.
+    failures: AtomicU64,
+}
+
+impl Detached {
+    /// Creates an empty list of tasks.
+    pub(crate) fn new() -> Detached {
+        Detached {
+            live: Mutex::new(Slab::new()),
+            failures: AtomicU64::new(0),
+        }
+    }
+
+    /// Registers a task, returning the key to remove it with.
+    pub(crate) fn insert(&self, id: u64) -> usize {
+        self.live.lock().insert(id)
+    }
+
+    /// Removes a task that completed or was cancelled.
+    pub(crate) fn remove(&self, key: usize) {
+        self.live.lock().try_remove(key);
+    }
+
+    /// Reports a failed task as a tracing event and to `hook`, if there is one.
+    pub(crate) fn fail(&self, failure: TaskFailure, hook: Option<&Hook<TaskFailure>>) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+        event!(
+            task = failure.id,
+            panicked = failure.panicked,
+            error = %failure.error,
+            "detached task failed"
+        );
+        if let Some(hook) = hook {
+            hook.call(&failure);
+        }
+    }
+
+    /// Returns the IDs of unfinished tasks.
+    pub(crate) fn ids(&self) -> Vec<u64> {
+        self.live.lock().iter().map(|(_, id)| *id).collect()
+    }
+
+    /// Returns the number of tasks that failed.
+    pub(crate) fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
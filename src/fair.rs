@@ -0,0 +1,173 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use async_task::Runnable;
+use parking_lot::Mutex;
+use slab::Slab;
+
+/// Virtual runtime a group with weight 1 accrues per nanosecond of poll time.
+const SCALE: u64 = 1024;
+
+/// Runnable tasks of weighted groups, shared fairly between the groups.
+///
+/// Every group accrues virtual runtime while its tasks are polled, at a rate inversely
+/// proportional to its weight. Runners always pick a task from the group with the least virtual
+/// runtime, so over time each group gets poll time in proportion to its weight.
+#[derive(Debug, Default)]
+pub(crate) struct FairQueue {
+    inner: Mutex<Inner>,
+
+    /// Number of groups with scheduled tasks, checked before locking.
+    ready_count: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    /// All groups, indexed by ID.
+    groups: Slab<GroupQueue>,
+
+    /// Groups with scheduled tasks, ordered by virtual runtime.
+    ready: BTreeSet<(u64, usize)>,
+
+    /// Virtual runtime of the group picked last.
+    ///
+    /// Groups that become ready start no lower than this, so that idle groups can't bank time.
+    min_vruntime: u64,
+}
+
+#[derive(Debug)]
+struct GroupQueue {
+    weight: u32,
+    vruntime: u64,
+
+    /// Bumped when the group is cancelled, so that tasks spawned before can't be scheduled.
+    generation: u64,
+
+    /// Total poll time in nanoseconds.
+    poll_time: u64,
+
+    /// Scheduled tasks in the order they were woken.
+    tasks: VecDeque<Runnable>,
+}
+
+impl FairQueue {
+    /// Registers a new group with weight 1 and returns its ID.
+    pub(crate) fn add(&self) -> usize {
+        self.inner.lock().groups.insert(GroupQueue {
+            weight: 1,
+            vruntime: 0,
+            generation: 0,
+            poll_time: 0,
+            tasks: VecDeque::new(),
+        })
+    }
+
+    /// Sets the weight of a group.
+    pub(crate) fn set_weight(&self, id: usize, weight: u32) {
+        self.inner.lock().groups[id].weight = weight.max(1);
+    }
+
+    /// Returns the total poll time of a group's tasks.
+    pub(crate) fn poll_time(&self, id: usize) -> Duration {
+        Duration::from_nanos(self.inner.lock().groups[id].poll_time)
+    }
+
+    /// Returns the current generation of a group.
+    pub(crate) fn generation(&self, id: usize) -> u64 {
+        self.inner.lock().groups[id].generation
+    }
+
+    /// Schedules a task of a group, or returns it back if the group has been cancelled since
+    /// the task was spawned in the given generation.
+    pub(crate) fn push(
+        &self,
+        id: usize,
+        generation: u64,
+        runnable: Runnable,
+    ) -> Result<(), Runnable> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let group = &mut inner.groups[id];
+        if group.generation != generation {
+            return Err(runnable);
+        }
+        group.tasks.push_back(runnable);
+
+        if group.tasks.len() == 1 {
+            group.vruntime = group.vruntime.max(inner.min_vruntime);
+            inner.ready.insert((group.vruntime, id));
+            self.ready_count.store(inner.ready.len(), Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Pops a task of the group with the least virtual runtime.
+    pub(crate) fn pop(&self) -> Option<Runnable> {
+        if self.ready_count.load(Ordering::SeqCst) == 0 {
+            return None;
+        }
+
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let (vruntime, id) = *inner.ready.iter().next()?;
+        inner.ready.remove(&(vruntime, id));
+        inner.min_vruntime = vruntime;
+
+        let group = &mut inner.groups[id];
+        let runnable = group.tasks.pop_front();
+        if !group.tasks.is_empty() {
+            inner.ready.insert((group.vruntime, id));
+        }
+        self.ready_count.store(inner.ready.len(), Ordering::SeqCst);
+        runnable
+    }
+
+    /// Charges a group for polling one of its tasks.
+    pub(crate) fn charge(&self, id: usize, elapsed: Duration) {
+        let nanos = u64::try_from(elapsed.as_nanos()).unwrap_or(u64::MAX);
+
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let group = &mut inner.groups[id];
+
+        // Keep the group's place in the ready set in sync with its virtual runtime.
+        let ready = !group.tasks.is_empty();
+        if ready {
+            inner.ready.remove(&(group.vruntime, id));
+        }
+        group.poll_time = group.poll_time.saturating_add(nanos);
+        group.vruntime = group
+            .vruntime
+            .saturating_add(nanos.saturating_mul(SCALE) / u64::from(group.weight));
+        if ready {
+            inner.ready.insert((group.vruntime, id));
+        }
+    }
+
+    /// Starts a new generation of a group and removes its scheduled tasks.
+    pub(crate) fn cancel(&self, id: usize) -> VecDeque<Runnable> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        let group = &mut inner.groups[id];
+        group.generation += 1;
+        if !group.tasks.is_empty() {
+            inner.ready.remove(&(group.vruntime, id));
+            self.ready_count.store(inner.ready.len(), Ordering::SeqCst);
+        }
+        std::mem::take(&mut group.tasks)
+    }
+
+    /// Removes all scheduled tasks.
+    pub(crate) fn take_all(&self) -> Vec<Runnable> {
+        let mut inner = self.inner.lock();
+        inner.ready.clear();
+        self.ready_count.store(0, Ordering::SeqCst);
+        inner
+            .groups
+            .iter_mut()
+            .flat_map(|(_, group)| group.tasks.drain(..))
+            .collect()
+    }
+}
@@ -0,0 +1,212 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use crate::atomic::AtomicU64;
+
+/// Number of bits of a value kept exactly by its bucket.
+///
+/// Every power of two is split into `2^SUB_BITS` buckets, which bounds the relative error to
+/// `2^-SUB_BITS`, a bit over 6%.
+const SUB_BITS: u32 = 4;
+
+/// Number of buckets per power of two.
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+
+/// Number of buckets needed to cover all `u64` values.
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+/// Returns the bucket a value falls into.
+fn bucket(value: u64) -> usize {
+    if value < SUB_BUCKETS as u64 {
+        return value as usize;
+    }
+    let exp = 63 - value.leading_zeros();
+    let sub = (value >> (exp - SUB_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exp - SUB_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+/// Returns the smallest value that falls into a bucket.
+fn lowest(bucket: usize) -> u64 {
+    if bucket < SUB_BUCKETS {
+        return bucket as u64;
+    }
+    let exp = (bucket / SUB_BUCKETS) as u32 + SUB_BITS - 1;
+    let sub = (bucket % SUB_BUCKETS) as u64;
+    (SUB_BUCKETS as u64 + sub) << (exp - SUB_BITS)
+}
+
+/// Returns the largest value that falls into a bucket.
+fn highest(bucket: usize) -> u64 {
+    if bucket + 1 < BUCKETS {
+        lowest(bucket + 1) - 1
+    } else {
+        u64::MAX
+    }
+}
+
+/// A histogram of how long tasks waited to be polled after getting scheduled.
+///
+/// Like an HDR histogram, it has logarithmically sized buckets, so that recorded latencies are
+/// known to within about 6% whether they are nanoseconds or seconds long.
+///
+/// Collected by executors configured with
+/// [`Builder::record_latency()`][crate::Builder::record_latency].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::Builder;
+///
+/// let ex = Builder::new().record_latency(true).build();
+///
+/// let task = ex.spawn(async {});
+/// assert!(ex.try_tick());
+///
+/// let histogram = ex.latency_histogram();
+/// assert_eq!(histogram.count(), 1);
+/// println!("p99 = {:?}", histogram.quantile(0.99));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    counts: Box<[u64]>,
+}
+
+impl LatencyHistogram {
+    /// Returns the number of recorded latencies.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    /// assert_eq!(ex.latency_histogram().count(), 0);
+    /// ```
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Returns the latency below which the given fraction of recorded latencies fall.
+    ///
+    /// The fraction is clamped between 0 and 1. Returns zero if nothing was recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().record_latency(true).build();
+    ///
+    /// let task = ex.spawn(async {});
+    /// assert!(ex.try_tick());
+    ///
+    /// let histogram = ex.latency_histogram();
+    /// assert!(histogram.quantile(0.5) <= histogram.max());
+    /// ```
+    pub fn quantile(&self, fraction: f64) -> Duration {
+        let count = self.count();
+        if count == 0 {
+            return Duration::ZERO;
+        }
+
+        // The rank of the value we're looking for, counting from 1.
+        let rank = ((fraction.clamp(0.0, 1.0) * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Duration::from_nanos(highest(bucket));
+            }
+        }
+        self.max()
+    }
+
+    /// Returns the smallest recorded latency, or zero if nothing was recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    /// assert_eq!(ex.latency_histogram().min(), Duration::ZERO);
+    /// ```
+    pub fn min(&self) -> Duration {
+        match self.counts.iter().position(|&n| n > 0) {
+            Some(bucket) => Duration::from_nanos(lowest(bucket)),
+            None => Duration::ZERO,
+        }
+    }
+
+    /// Returns the largest recorded latency, or zero if nothing was recorded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    /// assert_eq!(ex.latency_histogram().max(), Duration::ZERO);
+    /// ```
+    pub fn max(&self) -> Duration {
+        match self.counts.iter().rposition(|&n| n > 0) {
+            Some(bucket) => Duration::from_nanos(highest(bucket)),
+            None => Duration::ZERO,
+        }
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LatencyHistogram")
+            .field("count", &self.count())
+            .field("min", &self.min())
+            .field("p50", &self.quantile(0.5))
+            .field("p99", &self.quantile(0.99))
+            .field("max", &self.max())
+            .finish()
+    }
+}
+
+/// A histogram that latencies can be recorded into concurrently.
+pub(crate) struct AtomicHistogram {
+    counts: Box<[AtomicU64]>,
+}
+
+impl AtomicHistogram {
+    /// Creates an empty histogram.
+    pub(crate) fn new() -> AtomicHistogram {
+        AtomicHistogram {
+            counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    /// Records a latency.
+    pub(crate) fn record(&self, latency: Duration) {
+        let nanos = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.counts[bucket(nanos)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the histogram.
+    pub(crate) fn snapshot(&self) -> LatencyHistogram {
+        LatencyHistogram {
+            counts: self
+                .counts
+                .iter()
+                .map(|n| n.load(Ordering::Relaxed))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Debug for AtomicHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.snapshot().fmt(f)
+    }
+}
@@ -4,7 +4,6 @@
 //!
 //! ```
 //! use async_executor::Executor;
-//! use futures_lite::future;
 //!
 //! // Create a new executor.
 //! let ex = Executor::new();
@@ -15,33 +14,97 @@
 //! });
 //!
 //! // Run the executor until the task completes.
-//! future::block_on(ex.run(task));
+//! ex.block_on(task);
 //! ```
+//!
+//! # Features
+//!
+//! - `tracing`: instruments executors with the [`tracing`](https://docs.rs/tracing) crate. Every
+//!   task runs in a `task` span carrying its group name, ID and spawn location, which is a child
+//!   of the span it was spawned in. Every poll is a `poll` span inside it. Runners emit events
+//!   when they park, unpark and steal tasks, and executors emit an event when they shut down.
+//! - `console`: implies `tracing` and follows the instrumentation conventions of
+//!   [`console-subscriber`](https://docs.rs/console-subscriber), so that `tokio-console` can show
+//!   the tasks of an executor. Task spans are `runtime.spawn` spans instead, and every operation
+//!   on a task's waker is reported as a `runtime::waker` event.
+//!
+//! # Timers
+//!
+//! Timeouts, cancellation grace periods and supervisor backoff need to wake tasks at a deadline.
+//! By default, they are timed by a background thread named `async-executor-timer`, which is
+//! shared by the whole process. It is started the first time any timer is needed and keeps
+//! running until the process exits. To use the timer of another runtime instead, set
+//! [`Builder::sleep()`].
 
 #![warn(missing_docs, missing_debug_implementations, rust_2018_idioms)]
 
+#[macro_use]
+mod trace;
+
+mod actor;
+mod atomic;
+mod builder;
+mod cancel;
+#[cfg(feature = "console")]
+mod console;
+mod deadline;
+mod detached;
+mod fair;
+mod histogram;
+mod limit;
+mod local;
+mod pinned;
+mod registry;
+mod supervisor;
 mod taskqueue;
+mod timer;
+mod timing;
+mod wakes;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
 use std::marker::PhantomData;
-use std::rc::Rc;
-use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::mem;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::task::{Poll, Waker};
-use std::{
-    cell::Cell,
-    panic::{RefUnwindSafe, UnwindSafe},
-};
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread::{self, Thread, ThreadId};
+use std::time::{Duration, Instant};
 use std::{cell::RefCell, future::Future};
 
-use async_task::Runnable;
-
-use cache_padded::CachePadded;
+use atomic::AtomicU64;
+use builder::Config;
+use crossbeam_utils::CachePadded;
+use deadline::DeadlineCounters;
+use detached::Detached;
+use fair::FairQueue;
 use futures_lite::{future, prelude::*};
+use histogram::AtomicHistogram;
+use limit::TaskLimit;
 use parking_lot::{Mutex, RwLock};
+use pinned::Pinned;
+use registry::Registry;
 use slab::Slab;
-use taskqueue::{GlobalQueue, LocalQueue, LocalQueueHandle};
+use taskqueue::{GlobalQueue, Key, LocalQueue, LocalQueueHandle};
+use timer::Sleep;
+use timing::Timers;
+use wakes::Wakes;
+
+pub use actor::{Actor, Addr, MailboxMetrics, Reply, SendError, TrySendError};
+pub use builder::Builder;
+pub use cancel::{CancellationToken, Cancelled};
+pub use deadline::DeadlineMetrics;
+pub use detached::TaskFailure;
+pub use histogram::LatencyHistogram;
+pub use local::{spawn_local, LocalExecutor};
+pub use supervisor::{RestartStrategy, Supervisor, SupervisorHandle};
+pub use taskqueue::{LocalTaskQueue, QueueKind, TaskQueue};
+pub use timing::TaskTimes;
+pub use wakes::TaskWakes;
 
 #[doc(no_inline)]
-pub use async_task::Task;
+pub use async_task::{Runnable, Task};
 
 /// An async executor.
 ///
@@ -72,6 +135,9 @@ pub struct Executor<'a> {
     /// The executor state.
     state: once_cell::sync::OnceCell<Arc<State>>,
 
+    /// The configuration the state gets created with.
+    builder: Builder,
+
     /// Makes the `'a` lifetime invariant.
     _marker: PhantomData<std::cell::UnsafeCell<&'a ()>>,
 }
@@ -93,10 +159,7 @@ impl<'a> Executor<'a> {
     /// let ex = Executor::new();
     /// ```
     pub const fn new() -> Executor<'a> {
-        Executor {
-            state: once_cell::sync::OnceCell::new(),
-            _marker: PhantomData,
-        }
+        Builder::new().build()
     }
 
     /// Returns `true` if there are no unfinished tasks.
@@ -118,11 +181,17 @@ impl<'a> Executor<'a> {
     /// assert!(ex.is_empty());
     /// ```
     pub fn is_empty(&self) -> bool {
-        self.state().active.lock().is_empty()
+        self.state().active.is_empty()
     }
 
     /// Spawns a task onto the executor.
     ///
+    /// If this is called from a task with a [`CancellationToken`], the new task gets a child
+    /// token of it. Once that token fires and the
+    /// [grace period][Builder::cancel_grace_period()] has passed, the new task is dropped without
+    /// completing, and awaiting its [`Task`] panics. Use [`Task::fallible()`] to get `None`
+    /// instead.
+    ///
     /// # Examples
     ///
     /// ```
@@ -134,29 +203,654 @@ impl<'a> Executor<'a> {
     ///     println!("Hello world");
     /// });
     /// ```
+    ///
+    /// A task spawned from a cancelled task:
+    ///
+    /// ```
+    /// use async_executor::{CancellationToken, Executor};
+    /// use futures_lite::future;
+    ///
+    /// static EX: Executor<'_> = Executor::new();
+    /// let token = CancellationToken::new();
+    ///
+    /// let parent = EX.spawn_with_token(token.clone(), async {
+    ///     EX.spawn(future::pending::<()>()).fallible()
+    /// });
+    /// let child = EX.block_on(parent).unwrap();
+    ///
+    /// token.cancel();
+    /// assert_eq!(EX.block_on(child), None);
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
     pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
-        let mut active = self.state().active.lock();
+        let key = self.state().priority_key(0);
+        unsafe { self.spawn_inner(future, None, self.schedule(key)) }
+    }
 
-        // Remove the task from the set of active tasks when the future finishes.
-        let index = active.vacant_entry().key();
+    /// Spawns a task with a priority onto the executor.
+    ///
+    /// Executors created with [`QueueKind::Priority`] run tasks with a higher priority first.
+    /// Tasks spawned with [`Executor::spawn()`] have priority 0. With other kinds of queues, the
+    /// priority is ignored.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Builder, QueueKind};
+    ///
+    /// let ex = Builder::new().queue(QueueKind::Priority).build();
+    ///
+    /// let low = ex.spawn_with_priority(1, async { println!("second") });
+    /// let high = ex.spawn_with_priority(2, async { println!("first") });
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_with_priority<T: Send + 'a>(
+        &self,
+        priority: u32,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<T> {
+        let key = self.state().priority_key(priority);
+        unsafe { self.spawn_inner(future, None, self.schedule(key)) }
+    }
+
+    /// Spawns a task with a deadline onto the executor.
+    ///
+    /// Executors created with [`QueueKind::Deadline`] run the task with the earliest deadline
+    /// first. With other kinds of queues, the deadline doesn't affect the order in which tasks
+    /// run. Either way, whether the task completes in time is counted in
+    /// [`Executor::deadline_metrics()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::{Duration, Instant};
+    ///
+    /// use async_executor::{Builder, QueueKind};
+    ///
+    /// let ex = Builder::new().queue(QueueKind::Deadline).build();
+    ///
+    /// let now = Instant::now();
+    /// let later = ex.spawn_with_deadline(now + Duration::from_millis(20), async {
+    ///     println!("second");
+    /// });
+    /// let sooner = ex.spawn_with_deadline(now + Duration::from_millis(10), async {
+    ///     println!("first");
+    /// });
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_with_deadline<T: Send + 'a>(
+        &self,
+        deadline: Instant,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<T> {
         let state = self.state().clone();
+        let key = state.deadline_key(deadline);
         let future = async move {
-            let _guard = CallOnDrop(move || {
-                // TODO: use try_remove once https://github.com/tokio-rs/slab/pull/89 merged
-                let mut active = state.active.lock();
-                if active.contains(index) {
-                    drop(active.remove(index));
+            let output = future.await;
+            state.deadlines.record(deadline);
+            output
+        };
+        unsafe { self.spawn_inner(future, None, self.schedule(key)) }
+    }
+
+    /// Spawns a task that gets cancelled by a token.
+    ///
+    /// While the task runs, the token is returned by [`CancellationToken::current()`], so that
+    /// the task can wind down cooperatively once it fires. If the task is still running after the
+    /// [grace period][Builder::cancel_grace_period()], its future is dropped and the task
+    /// completes with [`Cancelled`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Cancelled, CancellationToken, Executor};
+    /// use futures_lite::future;
+    ///
+    /// let ex = Executor::new();
+    /// let token = CancellationToken::new();
+    ///
+    /// let task = ex.spawn_with_token(token.clone(), future::pending::<()>());
+    /// token.cancel();
+    /// assert_eq!(ex.block_on(task), Err(Cancelled));
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_with_token<T: Send + 'a>(
+        &self,
+        token: CancellationToken,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<Result<T, Cancelled>> {
+        self.spawn_cancellable(token, None, future)
+    }
+
+    /// Spawns a task that gets cancelled after a timeout.
+    ///
+    /// Once the timeout elapses, the task's token fires, and the task is cancelled like one
+    /// spawned with [`Executor::spawn_with_token()`]. The token is a child token of the current
+    /// task's token, if there is one.
+    ///
+    /// The timeout is timed by the executor's [timer](crate#timers).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::{Cancelled, Executor};
+    /// use futures_lite::future;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// let task = ex.spawn_with_timeout(Duration::from_secs(60), async { 1 + 2 });
+    /// assert_eq!(ex.block_on(task), Ok(3));
+    ///
+    /// let task = ex.spawn_with_timeout(Duration::from_millis(10), future::pending::<()>());
+    /// assert_eq!(ex.block_on(task), Err(Cancelled));
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_with_timeout<T: Send + 'a>(
+        &self,
+        timeout: Duration,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<Result<T, Cancelled>> {
+        let token = match CancellationToken::current() {
+            Some(token) => token.child_token(),
+            None => CancellationToken::new(),
+        };
+        self.spawn_cancellable(token, Some(Instant::now() + timeout), future)
+    }
+
+    /// Spawns a task that gets cancelled by a token, which fires at the deadline if there is one.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    fn spawn_cancellable<T: Send + 'a>(
+        &self,
+        token: CancellationToken,
+        deadline: Option<Instant>,
+        future: impl Future<Output = T> + Send + 'a,
+    ) -> Task<Result<T, Cancelled>> {
+        let grace = self.state().config.cancel_grace_period;
+        let sleep = self.state().config.sleep.clone();
+        let future = async move {
+            let run = async {
+                futures_lite::pin!(future);
+                let output = future::poll_fn(|cx| {
+                    let _current = token.enter();
+                    future.as_mut().poll(cx)
+                })
+                .await;
+                Ok(output)
+            };
+
+            // Drop the future once the grace period after cancellation has passed.
+            let cancel = async {
+                match deadline {
+                    Some(deadline) => {
+                        Sleep::until(deadline, sleep.as_ref())
+                            .or(token.cancelled())
+                            .await
+                    }
+                    None => token.cancelled().await,
                 }
-            });
-            future.await
+                token.cancel();
+                Sleep::until(Instant::now() + grace, sleep.as_ref()).await;
+                event!("cancelled task dropped after grace period");
+                Err(Cancelled)
+            };
+            run.or(cancel).await
         };
 
-        // Create the task and register it in the set of active tasks.
-        let (runnable, task) = unsafe { async_task::spawn_unchecked(future, self.schedule()) };
-        active.insert(runnable.waker());
+        // The task has its own token, so it doesn't inherit the current one.
+        let _exit = cancel::exit();
+        let key = self.state().priority_key(0);
+        unsafe { self.spawn_inner(future, None, self.schedule(key)) }
+    }
 
-        runnable.schedule();
-        task
+    /// Spawns a task that always runs on the given worker's thread.
+    ///
+    /// The future doesn't need to be `Send`: it is created by `factory` on the worker's thread
+    /// right before its first poll, and it's only ever polled and dropped on that thread. The
+    /// worker polls pinned tasks in the same loop as its other tasks. Worker IDs are returned by
+    /// [`Executor::worker_ids()`].
+    ///
+    /// Inside a pinned task, [`spawn_local()`] spawns more tasks pinned to the same worker.
+    ///
+    /// If the worker doesn't exist or stops running before the task completes, the task is
+    /// cancelled. If the worker's future returned by [`Executor::run()`] gets moved to another
+    /// thread, its pinned tasks stop running and are leaked when the worker stops.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::rc::Rc;
+    ///
+    /// use async_executor::Executor;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// future::block_on(ex.run(async {
+    ///     let worker = ex.worker_ids()[0];
+    ///
+    ///     // `Rc` is not `Send`.
+    ///     let task = ex.spawn_pinned(worker, || async {
+    ///         let n = Rc::new(1);
+    ///         *n + 2
+    ///     });
+    ///     assert_eq!(task.await, 3);
+    /// }));
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_pinned<F, Fut>(&self, worker: usize, factory: F) -> Task<Fut::Output>
+    where
+        F: FnOnce() -> Fut + Send + 'a,
+        Fut: Future + 'a,
+        Fut::Output: Send + 'a,
+    {
+        let pinned = self.state().workers.read().get(worker).cloned();
+        let pinned = pinned.unwrap_or_else(|| Arc::new(Pinned::closed()));
+        let future = pinned.wrap(self.state().clone(), factory);
+        let schedule = move |runnable| pinned.schedule(runnable);
+
+        // SAFETY: The future only gets created, polled and dropped on the worker's thread.
+        unsafe { self.spawn_inner(future, None, schedule) }
+    }
+
+    /// Spawns a task that runs to completion without a [`Task`] handle, reporting failures.
+    ///
+    /// If the future returns an error or panics, the failure is passed to the function set with
+    /// [`Builder::on_task_failure()`], if any, and reported as a `tracing` event. Nothing is
+    /// printed. Failures are counted in [`Executor::failed_tasks()`]. A panic doesn't propagate
+    /// to the runner that polled the task.
+    ///
+    /// Returns the ID of the task, which is listed in [`Executor::detached_tasks()`] until the
+    /// task completes and identifies it in [`Executor::task_times()`] and
+    /// [`Executor::task_wakes()`] as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// let id = ex.spawn_detached(async {
+    ///     std::fs::metadata("/nonexistent")?;
+    ///     Ok::<(), std::io::Error>(())
+    /// });
+    /// assert_eq!(ex.detached_tasks(), [id]);
+    ///
+    /// assert!(ex.try_tick());
+    /// assert!(ex.detached_tasks().is_empty());
+    /// assert_eq!(ex.failed_tasks(), 1);
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_detached<E>(&self, future: impl Future<Output = Result<(), E>> + Send + 'a) -> u64
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        let state = self.state().clone();
+        let id = state.next_task_id.fetch_add(1, Ordering::Relaxed);
+
+        // Unregister the task once it completes or gets cancelled, even before its first poll.
+        let slot = state.detached.insert(id);
+        let guard = {
+            let state = state.clone();
+            CallOnDrop(move || state.detached.remove(slot))
+        };
+
+        let future = async move {
+            let _guard = guard;
+            if let Err(failure) = detached::catch_failure(id, future).await {
+                let hook = state.config.on_task_failure.as_ref();
+                state.detached.fail(failure, hook);
+            }
+        };
+
+        let key = self.state().priority_key(0);
+        unsafe { self.state().spawn(id, future, None, self.schedule(key)) }.detach();
+        id
+    }
+
+    /// Spawns an actor and returns its address.
+    ///
+    /// The actor runs as a detached task that handles the messages in its mailbox one at a time.
+    /// Once the mailbox holds `capacity` messages, [`Addr::send()`] waits for room. The actor
+    /// stops after all of its addresses are dropped and the remaining messages are handled.
+    ///
+    /// The actor is listed in [`Executor::detached_tasks()`] under [`Addr::id()`] until it stops.
+    /// If it panics, the panic is reported like a failure of a task spawned with
+    /// [`Executor::spawn_detached()`], and its mailbox stops accepting messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Actor, Executor};
+    ///
+    /// struct Printer;
+    ///
+    /// impl Actor for Printer {
+    ///     type Message = String;
+    ///
+    ///     async fn handle(&mut self, message: String) {
+    ///         println!("{}", message);
+    ///     }
+    /// }
+    ///
+    /// let ex = Executor::new();
+    /// let addr = ex.spawn_actor(Printer, 16);
+    ///
+    /// ex.block_on(addr.send("Hello world".to_string())).unwrap();
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn_actor<A: Actor + 'a>(&self, actor: A, capacity: usize) -> Addr<A> {
+        let mailbox = Arc::new(actor::Mailbox::new(capacity));
+        let id = self.spawn_detached(actor::run(actor, mailbox.clone()));
+        Addr::new(mailbox, id)
+    }
+
+    /// Spawns a task that turns errors and panics of its future into failures.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub(crate) fn spawn_catching<E>(
+        &self,
+        future: impl Future<Output = Result<(), E>> + Send + 'a,
+    ) -> Task<Result<(), TaskFailure>>
+    where
+        E: Into<Box<dyn Error + Send + Sync>> + 'a,
+    {
+        let id = self.state().next_task_id.fetch_add(1, Ordering::Relaxed);
+        let future = detached::catch_failure(id, future);
+        let key = self.state().priority_key(0);
+        unsafe { self.state().spawn(id, future, None, self.schedule(key)) }
+    }
+
+    /// Returns the IDs of unfinished tasks spawned with [`Executor::spawn_detached()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// let id = ex.spawn_detached(async { Ok::<(), String>(()) });
+    /// assert_eq!(ex.detached_tasks(), [id]);
+    /// ```
+    pub fn detached_tasks(&self) -> Vec<u64> {
+        self.state().detached.ids()
+    }
+
+    /// Returns the number of tasks spawned with [`Executor::spawn_detached()`] that returned an
+    /// error or panicked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// ex.spawn_detached(async { Err("oops") });
+    /// assert!(ex.try_tick());
+    /// assert_eq!(ex.failed_tasks(), 1);
+    /// ```
+    pub fn failed_tasks(&self) -> u64 {
+        self.state().detached.failures()
+    }
+
+    /// Returns the IDs of the workers currently running the executor.
+    ///
+    /// Every call to [`Executor::run()`] is a worker while its future is alive. Tasks can be
+    /// pinned to a worker with [`Executor::spawn_pinned()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Executor::new();
+    /// assert!(ex.worker_ids().is_empty());
+    ///
+    /// future::block_on(ex.run(async {
+    ///     assert_eq!(ex.worker_ids().len(), 1);
+    /// }));
+    /// ```
+    pub fn worker_ids(&self) -> Vec<usize> {
+        self.state()
+            .workers
+            .read()
+            .iter()
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    /// Returns statistics about how many tasks spawned with a deadline completed in time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Instant;
+    ///
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// // This deadline has already passed.
+    /// let task = ex.spawn_with_deadline(Instant::now(), async {});
+    /// assert!(ex.try_tick());
+    ///
+    /// assert_eq!(ex.deadline_metrics().missed, 1);
+    /// ```
+    pub fn deadline_metrics(&self) -> DeadlineMetrics {
+        self.state().deadlines.metrics()
+    }
+
+    /// Returns the time spent polling each unfinished task so far.
+    ///
+    /// Always empty unless the executor was configured with [`Builder::time_tasks()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().time_tasks(true).build();
+    ///
+    /// let task = ex.spawn(future::yield_now());
+    /// assert!(ex.try_tick());
+    ///
+    /// let times = ex.task_times();
+    /// assert_eq!(times[0].polls, 1);
+    /// ```
+    pub fn task_times(&self) -> Vec<TaskTimes> {
+        self.state().timers.snapshot()
+    }
+
+    /// Returns how often each unfinished task has been woken so far.
+    ///
+    /// Always empty unless the executor was configured with [`Builder::track_wakes()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().track_wakes(true).build();
+    ///
+    /// let task = ex.spawn(future::yield_now());
+    /// assert!(ex.try_tick());
+    ///
+    /// let wakes = ex.task_wakes();
+    /// assert_eq!(wakes[0].wakes, 1);
+    /// assert_eq!(wakes[0].self_wakes, 1);
+    /// ```
+    pub fn task_wakes(&self) -> Vec<TaskWakes> {
+        self.state().wakes.snapshot()
+    }
+
+    /// Returns the unfinished tasks that have been waiting to be woken for at least `threshold`.
+    ///
+    /// A task that stays alive but never gets woken may be missing a wakeup, for example because
+    /// it returned [`Poll::Pending`] without storing its waker anywhere. Tasks waiting for
+    /// something that takes a long time show up here too, so these are only suspects.
+    ///
+    /// Always empty unless the executor was configured with [`Builder::track_wakes()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::Builder;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().track_wakes(true).build();
+    ///
+    /// let task = ex.spawn(future::pending::<()>());
+    /// assert!(ex.try_tick());
+    ///
+    /// assert_eq!(ex.suspected_lost_wakeups(Duration::ZERO).len(), 1);
+    /// ```
+    pub fn suspected_lost_wakeups(&self, threshold: Duration) -> Vec<TaskWakes> {
+        let mut wakes = self.task_wakes();
+        wakes.retain(|w| w.waiting.is_some_and(|waiting| waiting >= threshold));
+        wakes
+    }
+
+    /// Returns how many times tasks were woken after they had completed or were cancelled.
+    ///
+    /// Such wakes do nothing, but they hint at wakers that outlive what they were registered for.
+    /// Always 0 unless the executor was configured with [`Builder::track_wakes()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().track_wakes(true).build();
+    ///
+    /// let (s, r) = async_channel::bounded(1);
+    /// let task = ex.spawn(async move {
+    ///     // Keep a waker around after completing.
+    ///     let waker = future::poll_fn(|cx| std::task::Poll::Ready(cx.waker().clone())).await;
+    ///     s.send(waker).await.unwrap();
+    /// });
+    /// assert!(ex.try_tick());
+    ///
+    /// r.try_recv().unwrap().wake();
+    /// assert_eq!(ex.wakes_after_completion(), 1);
+    /// ```
+    pub fn wakes_after_completion(&self) -> u64 {
+        self.state().wakes.after_completion()
+    }
+
+    /// Returns a histogram of how long tasks waited to be polled after getting scheduled.
+    ///
+    /// Always empty unless the executor was configured with [`Builder::record_latency()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    ///
+    /// let ex = Builder::new().record_latency(true).build();
+    ///
+    /// let task = ex.spawn(async {});
+    /// assert!(ex.try_tick());
+    ///
+    /// assert_eq!(ex.latency_histogram().count(), 1);
+    /// ```
+    pub fn latency_histogram(&self) -> LatencyHistogram {
+        self.state().latency.snapshot()
+    }
+
+    /// Returns the latency histograms of the runners currently running the executor.
+    ///
+    /// Each histogram covers the tasks polled by one call to [`Executor::run()`], in no
+    /// particular order. Always empty unless the executor was configured with
+    /// [`Builder::record_runner_latency()`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Builder;
+    /// use futures_lite::future;
+    ///
+    /// let ex = Builder::new().record_runner_latency(true).build();
+    ///
+    /// let task = ex.spawn(async {});
+    /// future::block_on(ex.run(async {
+    ///     task.await;
+    ///     assert_eq!(ex.runner_latency_histograms()[0].count(), 1);
+    /// }));
+    /// ```
+    pub fn runner_latency_histograms(&self) -> Vec<LatencyHistogram> {
+        let runners = self.state().runner_latency.lock();
+        runners.iter().map(|(_, h)| h.snapshot()).collect()
+    }
+
+    /// Returns a handle to the named group of tasks, creating the group if it doesn't exist.
+    ///
+    /// Tasks spawned through the handle count against the group's limit on live tasks in
+    /// addition to the executor's limit. Groups share poll time in proportion to their weights,
+    /// and take turns with tasks outside of groups.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    /// let group = ex.group("db");
+    ///
+    /// let task = group.spawn(async {
+    ///     println!("Hello world");
+    /// });
+    /// ```
+    pub fn group(&self, name: &str) -> TaskGroup<'_, 'a> {
+        let state = self.state();
+        let group = state
+            .groups
+            .lock()
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(Group::new(name, state.fair.add())))
+            .clone();
+
+        TaskGroup {
+            executor: self,
+            group,
+        }
+    }
+
+    /// Sets the maximum number of tasks that may be live at once, or removes the limit.
+    ///
+    /// Tasks spawned past the limit are queued without being polled until a live task completes
+    /// or gets cancelled. Lowering the limit never cancels tasks that are already live.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    /// ex.set_max_tasks(Some(1));
+    ///
+    /// let t1 = ex.spawn(async {});
+    /// let t2 = ex.spawn(async {});
+    ///
+    /// assert!(ex.try_tick()); // runs the first task
+    /// assert!(ex.try_tick()); // the first task completed, so the second one is admitted
+    /// assert!(ex.is_empty());
+    /// ```
+    pub fn set_max_tasks(&self, max: Option<usize>) {
+        let state = self.state();
+        state.schedule_admitted(state.limit.set_max(max));
     }
 
     /// Attempts to run a task if at least one is scheduled.
@@ -177,12 +871,13 @@ impl<'a> Executor<'a> {
     /// assert!(ex.try_tick()); // a task was found
     /// ```
     pub fn try_tick(&self) -> bool {
-        match self.state().queue.pop() {
+        let state = self.state();
+        match state.queue.pop().or_else(|| state.fair.pop()) {
             None => false,
             Some(runnable) => {
                 // Notify another ticker now to pick up where this ticker left off, just in case
                 // running the task takes a long time.
-                self.state().notify();
+                self.state().notify_unless_searching();
 
                 // Run the task.
                 runnable.run();
@@ -233,15 +928,13 @@ impl<'a> Executor<'a> {
     /// ```
     pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
         let mut runner = Runner::new(self.state().clone());
-        runner.set_tls_active();
-        let _guard = CallOnDrop(clear_tls);
+        let _guard = runner.enter_tls();
         // A future that runs tasks forever.
         let run_forever = async {
             loop {
                 for _ in 0..200 {
                     let runnable = runner.runnable().await;
-                    let yielded = runnable.run();
-                    JUST_YIELDED.with(|v| v.replace(yielded));
+                    runnable.run();
                 }
                 future::yield_now().await;
             }
@@ -251,39 +944,174 @@ impl<'a> Executor<'a> {
         future.or(run_forever).await
     }
 
-    /// Returns a function that schedules a runnable task when it gets woken up.
-    fn schedule(&self) -> impl Fn(Runnable) + Send + Sync + 'static {
+    /// Blocks the current thread on a future, running the executor until it completes.
+    ///
+    /// This is a shorthand for `future::block_on(ex.run(future))` that parks the thread as a
+    /// sleeping runner of the executor, rather than through a separate parking mechanism.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    ///
+    /// let ex = Executor::new();
+    ///
+    /// let task = ex.spawn(async { 1 + 2 });
+    /// let res = ex.block_on(async { task.await * 2 });
+    ///
+    /// assert_eq!(res, 6);
+    /// ```
+    pub fn block_on<T>(&self, future: impl Future<Output = T>) -> T {
+        let mut runner = Runner::new(self.state().clone());
+        let _guard = runner.enter_tls();
+
+        // The main future and the runner get separate wakers so that the main future is only
+        // polled when it was woken.
+        let main = Arc::new(Unparker::new());
+        let main_waker = Waker::from(main.clone());
+        let tasks = Arc::new(Unparker::new());
+        let tasks_waker = Waker::from(tasks.clone());
+
+        // Runs one task per poll. It is kept alive across polls so that the search for the next
+        // task picks up where it left off.
+        let run_tasks = async {
+            loop {
+                runner.runnable().await.run();
+                future::yield_now().await;
+            }
+        };
+
+        futures_lite::pin!(future);
+        futures_lite::pin!(run_tasks);
+        main.notified.store(true, Ordering::SeqCst);
+        tasks.notified.store(true, Ordering::SeqCst);
+        loop {
+            if main.notified.swap(false, Ordering::SeqCst) {
+                let cx = &mut Context::from_waker(&main_waker);
+                if let Poll::Ready(output) = future.as_mut().poll(cx) {
+                    return output;
+                }
+            }
+
+            if tasks.notified.swap(false, Ordering::SeqCst) {
+                // This never completes. While it waits, the runner is registered as a sleeper, so
+                // the executor unparks the thread when a task gets scheduled.
+                let cx = &mut Context::from_waker(&tasks_waker);
+                let _ = run_tasks.as_mut().poll(cx);
+            }
+
+            while !main.notified.load(Ordering::SeqCst) && !tasks.notified.load(Ordering::SeqCst) {
+                thread::park();
+            }
+        }
+    }
+
+    /// Runs the executor on the current thread forever.
+    ///
+    /// This is meant for worker threads that run the executor until the process exits.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::Executor;
+    /// use std::thread;
+    ///
+    /// static EX: Executor<'_> = Executor::new();
+    ///
+    /// for _ in 0..4 {
+    ///     thread::spawn(|| EX.run_forever());
+    /// }
+    ///
+    /// let task = EX.spawn(async { 1 + 2 });
+    /// assert_eq!(EX.block_on(task), 3);
+    /// ```
+    pub fn run_forever(&self) -> ! {
+        self.block_on(future::pending())
+    }
+
+    /// Spawns a task, optionally as a member of a group.
+    ///
+    /// # Safety
+    ///
+    /// If `future` is not `Send`, its task must only be run on one thread, and `schedule` must
+    /// make sure of that.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    unsafe fn spawn_inner<T>(
+        &self,
+        future: impl Future<Output = T> + 'a,
+        group: Option<Arc<Group>>,
+        schedule: impl Fn(Runnable) + Send + Sync + 'static,
+    ) -> Task<T> {
+        let id = self.state().next_task_id.fetch_add(1, Ordering::Relaxed);
+        self.state().spawn(id, future, group, schedule)
+    }
+
+    /// Returns a function that schedules a runnable task with the given key when it gets woken
+    /// up.
+    fn schedule(&self, key: Key) -> impl Fn(Runnable) + Send + Sync + 'static {
         let state = self.state().clone();
 
         // Try to push to the local queue. If it doesn't work, push to the global queue.
         move |runnable| {
-            if let Err(runnable) = try_push_tls(&state, runnable) {
-                state.queue.push(runnable);
-                state.notify();
+            if let Err(runnable) = try_push_tls(&state, runnable, key) {
+                state.queue.push(runnable, key);
+                state.notify_unless_searching();
             }
         }
     }
 
+    /// Returns a function that schedules a runnable task of a group when it gets woken up.
+    ///
+    /// Tasks spawned before the group gets cancelled are dropped instead.
+    fn schedule_group(&self, group: &Group) -> impl Fn(Runnable) + Send + Sync + 'static {
+        let state = self.state().clone();
+        let id = group.id;
+        let generation = state.fair.generation(id);
+
+        move |runnable| match state.fair.push(id, generation, runnable) {
+            Ok(()) => state.notify(),
+            Err(runnable) => drop(runnable),
+        }
+    }
+
     /// Returns a reference to the inner state.
     fn state(&self) -> &Arc<State> {
-        self.state.get_or_init(|| Arc::new(State::new()))
+        self.state
+            .get_or_init(|| Arc::new(State::new(&self.builder.config)))
     }
 }
 
-thread_local! {
-    static JUST_YIELDED: Cell<bool> = Cell::new(false);
-}
-
 impl Drop for Executor<'_> {
     fn drop(&mut self) {
         if let Some(state) = self.state.get() {
-            let mut active = state.active.lock();
-            for w in active.drain() {
+            event!("executor shutting down");
+            for w in state.active.drain() {
                 w.wake();
             }
-            drop(active);
 
-            while state.queue.pop().is_some() {}
+            // Tasks waiting for a slot were never scheduled, so drop them directly. Dropping a task
+            // frees its slots, which may admit more tasks, so repeat until everything is gone.
+            loop {
+                let pending = state.take_pending();
+                let mut dropped = !pending.is_empty();
+                drop(pending);
+
+                while state.queue.pop().is_some() {
+                    dropped = true;
+                }
+                let fair = state.fair.take_all();
+                dropped |= !fair.is_empty();
+                drop(fair);
+                if !dropped {
+                    break;
+                }
+            }
+
+            // Untracked tasks that are still alive may get scheduled later. Leak the state so that
+            // their futures are never dropped after the borrows they hold have ended.
+            if !state.active.is_tracked() && !state.active.is_empty() {
+                mem::forget(state.clone());
+            }
         }
     }
 }
@@ -294,198 +1122,207 @@ impl<'a> Default for Executor<'a> {
     }
 }
 
-/// A thread-local executor.
+/// A handle to a named group of tasks on an [`Executor`].
 ///
-/// The executor can only be run on the thread that created it.
+/// Created by [`Executor::group()`].
 ///
 /// # Examples
 ///
+/// Allow at most two live tasks in a group:
+///
 /// ```
-/// use async_executor::LocalExecutor;
-/// use futures_lite::future;
+/// use async_executor::Executor;
 ///
-/// let local_ex = LocalExecutor::new();
+/// let ex = Executor::new();
+/// let group = ex.group("db");
+/// group.set_max_tasks(Some(2));
 ///
-/// future::block_on(local_ex.run(async {
-///     println!("Hello world!");
-/// }));
+/// for _ in 0..10 {
+///     group.spawn(async {}).detach();
+/// }
 /// ```
 #[derive(Debug)]
-pub struct LocalExecutor<'a> {
-    /// The inner executor.
-    inner: once_cell::unsync::OnceCell<Executor<'a>>,
+pub struct TaskGroup<'e, 'a> {
+    /// The executor the group belongs to.
+    executor: &'e Executor<'a>,
 
-    /// Makes the type `!Send` and `!Sync`.
-    _marker: PhantomData<Rc<()>>,
+    /// The group state.
+    group: Arc<Group>,
 }
 
-impl UnwindSafe for LocalExecutor<'_> {}
-impl RefUnwindSafe for LocalExecutor<'_> {}
-
-impl<'a> LocalExecutor<'a> {
-    /// Creates a single-threaded executor.
+impl<'a> TaskGroup<'_, 'a> {
+    /// Returns the name of the group.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
+    /// use async_executor::Executor;
     ///
-    /// let local_ex = LocalExecutor::new();
+    /// let ex = Executor::new();
+    /// assert_eq!(ex.group("db").name(), "db");
     /// ```
-    pub const fn new() -> LocalExecutor<'a> {
-        LocalExecutor {
-            inner: once_cell::unsync::OnceCell::new(),
-            _marker: PhantomData,
-        }
+    pub fn name(&self) -> &str {
+        &self.group.name
     }
 
-    /// Returns `true` if there are no unfinished tasks.
+    /// Spawns a task onto the executor as a member of the group.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
+    /// use async_executor::Executor;
     ///
-    /// let local_ex = LocalExecutor::new();
-    /// assert!(local_ex.is_empty());
+    /// let ex = Executor::new();
     ///
-    /// let task = local_ex.spawn(async {
+    /// let task = ex.group("db").spawn(async {
     ///     println!("Hello world");
     /// });
-    /// assert!(!local_ex.is_empty());
-    ///
-    /// assert!(local_ex.try_tick());
-    /// assert!(local_ex.is_empty());
     /// ```
-    pub fn is_empty(&self) -> bool {
-        self.inner().is_empty()
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn<T: Send + 'a>(&self, future: impl Future<Output = T> + Send + 'a) -> Task<T> {
+        // Charge the group for the time spent polling the task.
+        let state = self.executor.state().clone();
+        let id = self.group.id;
+        let future = async move {
+            futures_lite::pin!(future);
+            future::poll_fn(|cx| {
+                let start = Instant::now();
+                let poll = future.as_mut().poll(cx);
+                state.fair.charge(id, start.elapsed());
+                poll
+            })
+            .await
+        };
+
+        let schedule = self.executor.schedule_group(&self.group);
+        unsafe {
+            self.executor
+                .spawn_inner(future, Some(self.group.clone()), schedule)
+        }
     }
 
-    /// Spawns a task onto the executor.
+    /// Sets the weight of the group.
+    ///
+    /// While several groups have tasks to run, each of them gets poll time in proportion to its
+    /// weight. The default weight is 1, and a weight of 0 is treated as 1.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
+    /// use async_executor::Executor;
     ///
-    /// let local_ex = LocalExecutor::new();
+    /// let ex = Executor::new();
     ///
-    /// let task = local_ex.spawn(async {
-    ///     println!("Hello world");
-    /// });
+    /// // Give tenant A three times as much poll time as tenant B.
+    /// ex.group("tenant-a").set_weight(3);
+    /// ex.group("tenant-b").set_weight(1);
     /// ```
-    pub fn spawn<T: 'a>(&self, future: impl Future<Output = T> + 'a) -> Task<T> {
-        let mut active = self.inner().state().active.lock();
-
-        // Remove the task from the set of active tasks when the future finishes.
-        let index = active.vacant_entry().key();
-        let state = self.inner().state().clone();
-        let future = async move {
-            let _guard = CallOnDrop(move || drop(state.active.lock().remove(index)));
-            future.await
-        };
-
-        // Create the task and register it in the set of active tasks.
-        let (runnable, task) = unsafe { async_task::spawn_unchecked(future, self.schedule()) };
-        active.insert(runnable.waker());
-
-        runnable.schedule();
-        task
+    pub fn set_weight(&self, weight: u32) {
+        self.executor.state().fair.set_weight(self.group.id, weight);
     }
 
-    /// Attempts to run a task if at least one is scheduled.
-    ///
-    /// Running a scheduled task means simply polling its future once.
+    /// Returns the total time spent polling tasks of the group.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
+    /// use async_executor::Executor;
     ///
-    /// let ex = LocalExecutor::new();
-    /// assert!(!ex.try_tick()); // no tasks to run
+    /// let ex = Executor::new();
+    /// let group = ex.group("db");
     ///
-    /// let task = ex.spawn(async {
-    ///     println!("Hello world");
-    /// });
-    /// assert!(ex.try_tick()); // a task was found
+    /// let task = group.spawn(async {});
+    /// assert!(ex.try_tick());
+    ///
+    /// println!("polled for {:?}", group.poll_time());
     /// ```
-    pub fn try_tick(&self) -> bool {
-        self.inner().try_tick()
+    pub fn poll_time(&self) -> Duration {
+        self.executor.state().fair.poll_time(self.group.id)
     }
 
-    /// Runs a single task.
+    /// Cancels all tasks currently in the group.
     ///
-    /// Running a task means simply polling its future once.
-    ///
-    /// If no tasks are scheduled when this method is called, it will wait until one is scheduled.
+    /// Cancelled tasks are dropped without being polled again, like tasks of a dropped executor.
+    /// Tasks spawned into the group afterwards are not affected.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
+    /// use async_executor::Executor;
     /// use futures_lite::future;
     ///
-    /// let ex = LocalExecutor::new();
+    /// let ex = Executor::new();
+    /// let group = ex.group("db");
     ///
-    /// let task = ex.spawn(async {
-    ///     println!("Hello world");
-    /// });
-    /// future::block_on(ex.tick()); // runs the task
+    /// let task = group.spawn(future::pending::<()>());
+    /// group.cancel();
+    /// assert!(future::block_on(task.cancel()).is_none());
     /// ```
-    pub async fn tick(&self) {
-        self.inner().tick().await
+    pub fn cancel(&self) {
+        let state = self.executor.state();
+
+        // Tasks that are scheduled or waiting for a slot can be dropped right away.
+        drop(state.fair.cancel(self.group.id));
+        drop(self.group.limit.take_pending());
+
+        // Other tasks get dropped by the schedule function once woken.
+        let wakers: Vec<Waker> = self
+            .group
+            .members
+            .lock()
+            .iter()
+            .map(|(_, waker)| waker.clone())
+            .collect();
+        for waker in wakers {
+            waker.wake();
+        }
     }
 
-    /// Runs the executor until the given future completes.
+    /// Sets the maximum number of tasks in the group that may be live at once, or removes the
+    /// limit.
+    ///
+    /// Tasks spawned past the limit are queued without being polled until a live task in the
+    /// group completes or gets cancelled.
     ///
     /// # Examples
     ///
     /// ```
-    /// use async_executor::LocalExecutor;
-    /// use futures_lite::future;
+    /// use async_executor::Executor;
     ///
-    /// let local_ex = LocalExecutor::new();
+    /// let ex = Executor::new();
+    /// let group = ex.group("db");
+    /// group.set_max_tasks(Some(1));
     ///
-    /// let task = local_ex.spawn(async { 1 + 2 });
-    /// let res = future::block_on(local_ex.run(async { task.await * 2 }));
+    /// let t1 = group.spawn(async {});
+    /// let t2 = group.spawn(async {});
+    /// let t3 = ex.spawn(async {}); // not a member of the group
     ///
-    /// assert_eq!(res, 6);
+    /// assert!(ex.try_tick());
+    /// assert!(ex.try_tick());
+    /// assert!(ex.try_tick());
+    /// assert!(ex.is_empty());
     /// ```
-    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
-        self.inner().run(future).await
-    }
-
-    /// Returns a function that schedules a runnable task when it gets woken up.
-    fn schedule(&self) -> impl Fn(Runnable) + Send + Sync + 'static {
-        let state = self.inner().state().clone();
-        move |runnable| {
-            state.queue.push(runnable);
-            state.notify();
-        }
-    }
-
-    /// Returns a reference to the inner executor.
-    fn inner(&self) -> &Executor<'a> {
-        self.inner.get_or_init(Executor::new)
-    }
-}
-
-impl<'a> Default for LocalExecutor<'a> {
-    fn default() -> LocalExecutor<'a> {
-        LocalExecutor::new()
+    pub fn set_max_tasks(&self, max: Option<usize>) {
+        let state = self.executor.state();
+        state.admit_to_group(self.group.limit.set_max(max));
     }
 }
 
 /// The state of a executor.
 #[derive(Debug)]
 struct State {
+    /// The executor configuration.
+    config: Config,
+
     /// The global queue.
     queue: CachePadded<GlobalQueue>,
 
     /// Count of searching runners.
     searching_count: CachePadded<AtomicUsize>,
 
+    /// Count of runners.
+    runner_count: AtomicUsize,
+
     /// Local queues created by runners.
     local_queues: CachePadded<RwLock<Slab<LocalQueueHandle>>>,
 
@@ -496,27 +1333,327 @@ struct State {
     sleepers: CachePadded<Mutex<Sleepers>>,
 
     /// Currently active tasks.
-    active: CachePadded<Mutex<Slab<Waker>>>,
+    active: Registry,
+
+    /// Limit on live tasks across the whole executor.
+    limit: TaskLimit<Pending>,
+
+    /// Named groups of tasks.
+    groups: Mutex<HashMap<String, Arc<Group>>>,
+
+    /// The instant deadlines are measured from when ordering tasks by deadline.
+    epoch: Instant,
+
+    /// Statistics about tasks spawned with a deadline.
+    deadlines: DeadlineCounters,
+
+    /// Runnable tasks of groups.
+    fair: FairQueue,
+
+    /// The ID of the next spawned task.
+    next_task_id: AtomicU64,
+
+    /// Poll times of unfinished tasks, if timing is enabled.
+    timers: Arc<Timers>,
+
+    /// Wake counters of unfinished tasks, if enabled.
+    wakes: Arc<Wakes>,
+
+    /// How long tasks waited to be polled after getting scheduled, if recorded.
+    latency: AtomicHistogram,
+
+    /// Latency histograms of runners, if recorded.
+    runner_latency: Mutex<Slab<Arc<AtomicHistogram>>>,
+
+    /// Tasks pinned to each runner, keyed by worker ID.
+    workers: RwLock<Slab<Arc<Pinned>>>,
+
+    /// Unfinished tasks spawned with [`Executor::spawn_detached()`].
+    detached: Detached,
 }
 
 impl State {
     /// Creates state for a new executor.
-    fn new() -> State {
+    fn new(config: &Config) -> State {
+        let epoch = Instant::now();
         State {
-            queue: GlobalQueue::default().into(),
+            config: config.clone(),
+            queue: config
+                .custom_queue
+                .map_or_else(|| GlobalQueue::new(config.queue), |custom| custom())
+                .into(),
             searching_count: AtomicUsize::new(0).into(),
+            runner_count: AtomicUsize::new(0),
             local_queues: RwLock::new(Slab::new()).into(),
             notified: AtomicBool::new(true).into(),
-            sleepers: parking_lot::Mutex::new(Sleepers {
-                count: 0,
-                wakers: Vec::new(),
-                free_ids: Vec::new(),
-            })
-            .into(),
-            active: Mutex::new(Slab::new()).into(),
+            sleepers: Mutex::new(Sleepers::new()).into(),
+            active: Registry::new(config.track_tasks),
+            limit: TaskLimit::new(),
+            groups: Mutex::new(HashMap::new()),
+            epoch,
+            deadlines: DeadlineCounters::default(),
+            fair: FairQueue::default(),
+            next_task_id: AtomicU64::new(0),
+            timers: Arc::new(Timers::default()),
+            wakes: Arc::new(Wakes::new(epoch)),
+            latency: AtomicHistogram::new(),
+            runner_latency: Mutex::new(Slab::new()),
+            workers: RwLock::new(Slab::new()),
+            detached: Detached::new(),
+        }
+    }
+
+    /// Spawns a task with an ID taken from `next_task_id`, optionally as a member of a group.
+    ///
+    /// # Safety
+    ///
+    /// The same as for [`Executor::spawn_inner()`], and the executor must be dropped before
+    /// anything `future` borrows.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    unsafe fn spawn<T>(
+        self: &Arc<Self>,
+        id: u64,
+        future: impl Future<Output = T>,
+        group: Option<Arc<Group>>,
+        schedule: impl Fn(Runnable) + Send + Sync + 'static,
+    ) -> Task<T> {
+        let state = self;
+        let admission = Arc::new(Admission::new(group));
+
+        // Run the task in its own span, a child of the current one.
+        #[cfg(feature = "tracing")]
+        let future = {
+            let name = admission.group.as_ref().map(|group| &*group.name);
+            let span = trace::task_span(id, name, std::panic::Location::caller());
+            trace::instrument(span, future)
+        };
+
+        // Create the task and register it in the set of active tasks.
+        let (runnable, task) = state.active.insert(|index| {
+            // Members of a group are registered so that the group can be cancelled as a whole.
+            let mut members = admission.group.as_ref().map(|group| group.members.lock());
+            let member = members.as_mut().map(|m| m.vacant_entry().key());
+
+            // Remove the task from the set of active tasks and give back its slots when the
+            // future is dropped. The guard lives outside the inner future so that it also runs
+            // for tasks that get cancelled before their first poll.
+            let guard = {
+                let state = state.clone();
+                let admission = admission.clone();
+                CallOnDrop(move || {
+                    state.active.remove(index);
+                    if let (Some(group), Some(member)) = (&admission.group, member) {
+                        let waker = group.members.lock().try_remove(member);
+                        drop(waker);
+                    }
+                    state.release(&admission);
+                })
+            };
+
+            // Tasks spawned by a task with a cancellation token get a child token, and get
+            // dropped once it fires and the grace period has passed.
+            let token = cancel::inherited();
+            let dropped = token.as_ref().map(|_| Arc::new(AtomicBool::new(false)));
+
+            // Time every poll if enabled.
+            let timer = state.config.time_tasks.then(|| state.timers.register(id));
+
+            // Count wakes if enabled.
+            let mut wakes = state.config.track_wakes.then(|| state.wakes.register(id));
+
+            // Remember when the task was last scheduled if latency is recorded.
+            let scheduled = state
+                .config
+                .record_latency
+                .then(|| Arc::new(AtomicU64::new(0)));
+            let schedule = {
+                let scheduled = scheduled.clone();
+                let dropped = dropped.clone();
+                let epoch = state.epoch;
+                move |runnable| {
+                    // A dropped future can't complete, so drop the task as well.
+                    if dropped.as_ref().is_some_and(|d| d.load(Ordering::SeqCst)) {
+                        drop(runnable);
+                        return;
+                    }
+                    if let Some(scheduled) = &scheduled {
+                        scheduled.store(nanos_since(epoch), Ordering::Relaxed);
+                    }
+                    schedule(runnable)
+                }
+            };
+
+            let future = {
+                let state = state.clone();
+                async move {
+                    let _guard = guard;
+                    if timer.is_none() && scheduled.is_none() && wakes.is_none() && token.is_none()
+                    {
+                        return future.await;
+                    }
+
+                    let grace = state.config.cancel_grace_period;
+                    let sleep = state.config.sleep.clone();
+                    let expired = token.clone().map(|token| async move {
+                        token.cancelled().await;
+                        Sleep::until(Instant::now() + grace, sleep.as_ref()).await;
+                    });
+                    let future = Some(future);
+                    futures_lite::pin!(expired, future);
+                    let output = future::poll_fn(|cx| {
+                        let _current = token.as_ref().map(CancellationToken::enter);
+
+                        // Drop the future in place, on the thread polling it, and have the task
+                        // dropped when it gets scheduled.
+                        let fired = expired.as_mut().as_pin_mut().map(|e| e.poll(cx));
+                        if fired.is_some_and(|poll| poll.is_ready()) {
+                            event!("task dropped after grace period of inherited token");
+                            expired.set(None);
+                            future.set(None);
+                            if let Some(dropped) = &dropped {
+                                dropped.store(true, Ordering::SeqCst);
+                            }
+                        }
+                        let mut future = match future.as_mut().as_pin_mut() {
+                            Some(future) => future,
+                            None => {
+                                cx.waker().wake_by_ref();
+                                return Poll::Pending;
+                            }
+                        };
+
+                        if let Some(scheduled) = &scheduled {
+                            let scheduled = scheduled.load(Ordering::Relaxed);
+                            let latency = nanos_since(state.epoch).saturating_sub(scheduled);
+                            state.record_latency(Duration::from_nanos(latency));
+                        }
+                        let mut poll = |cx: &mut Context<'_>| match &timer {
+                            None => future.as_mut().poll(cx),
+                            Some(timer) => timer.measure(|| future.as_mut().poll(cx)),
+                        };
+                        match &mut wakes {
+                            None => poll(cx),
+                            Some(wakes) => wakes.poll(cx, poll),
+                        }
+                    })
+                    .await;
+
+                    if let (Some(timer), Some(hook)) = (&timer, &state.config.on_task_complete) {
+                        hook.call(&timer.times());
+                    }
+                    output
+                }
+            };
+
+            let (runnable, task) = async_task::spawn_unchecked(future, schedule);
+            if let Some(members) = &mut members {
+                members.insert(runnable.waker());
+            }
+            (runnable, task)
+        });
+
+        // Schedule the task, or queue it until it gets a slot.
+        state.admit(runnable, admission);
+        task
+    }
+
+    /// Records how long a task waited to be polled after getting scheduled.
+    ///
+    /// The latency also counts towards the innermost runner of this executor on the current
+    /// thread, if runners record their own latency.
+    fn record_latency(self: &Arc<Self>, latency: Duration) {
+        self.latency.record(latency);
+
+        if self.config.record_runner_latency {
+            TLS.with(|tls| {
+                if let Ok(tls) = tls.try_borrow() {
+                    let tlsdata = tls.iter().rev().find(|d| Arc::ptr_eq(self, &d.state));
+                    if let Some(histogram) = tlsdata.and_then(|d| d.latency.as_ref()) {
+                        histogram.record(latency);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Returns the key that orders a task with the given priority in the global queue.
+    fn priority_key(&self, priority: u32) -> Key {
+        match self.config.queue {
+            QueueKind::Priority => Key::from(u32::MAX - priority),
+            _ => Key::MAX,
+        }
+    }
+
+    /// Returns the key that orders a task with the given deadline in the global queue.
+    fn deadline_key(&self, deadline: Instant) -> Key {
+        match self.config.queue {
+            QueueKind::Deadline => {
+                // Deadlines before the epoch are all equally urgent.
+                let nanos = deadline.saturating_duration_since(self.epoch).as_nanos();
+                Key::try_from(nanos).unwrap_or(Key::MAX - 1)
+            }
+            _ => self.priority_key(0),
+        }
+    }
+
+    /// Schedules a newly spawned task once it has a slot in its group and in the executor.
+    fn admit(&self, runnable: Runnable, admission: Arc<Admission>) {
+        match &admission.group {
+            None => self.admit_to_executor(runnable, admission),
+            Some(group) => {
+                let group = group.clone();
+                if let Some(entry) = group.limit.acquire((runnable, admission)) {
+                    self.admit_to_group(vec![entry]);
+                }
+            }
+        }
+    }
+
+    /// Moves tasks that got a slot in their group on to the executor's limit.
+    fn admit_to_group(&self, admitted: Vec<Pending>) {
+        for (runnable, admission) in admitted {
+            admission.held.fetch_or(GROUP_SLOT, Ordering::SeqCst);
+            self.admit_to_executor(runnable, admission);
+        }
+    }
+
+    /// Schedules a task once it has a slot in the executor.
+    fn admit_to_executor(&self, runnable: Runnable, admission: Arc<Admission>) {
+        if let Some(entry) = self.limit.acquire((runnable, admission)) {
+            self.schedule_admitted(vec![entry]);
+        }
+    }
+
+    /// Schedules tasks that got a slot in the executor.
+    fn schedule_admitted(&self, admitted: Vec<Pending>) {
+        for (runnable, admission) in admitted {
+            admission.held.fetch_or(EXECUTOR_SLOT, Ordering::SeqCst);
+            runnable.schedule();
+        }
+    }
+
+    /// Gives back the slots held by a finished or cancelled task.
+    fn release(&self, admission: &Admission) {
+        let held = admission.held.swap(0, Ordering::SeqCst);
+        if held & EXECUTOR_SLOT != 0 {
+            self.schedule_admitted(self.limit.release());
+        }
+        if held & GROUP_SLOT != 0 {
+            if let Some(group) = &admission.group {
+                self.admit_to_group(group.limit.release());
+            }
         }
     }
 
+    /// Removes all tasks still waiting for a slot.
+    fn take_pending(&self) -> Vec<Pending> {
+        let mut pending = self.limit.take_pending();
+        for group in self.groups.lock().values() {
+            pending.extend(group.limit.take_pending());
+        }
+        pending
+    }
+
     /// Notifies a sleeping ticker.
     #[inline]
     fn notify(&self) {
@@ -531,83 +1668,221 @@ impl State {
             }
         }
     }
+
+    /// Notifies a sleeping ticker unless some runners are searching for tasks.
+    ///
+    /// Searching runners will find the task, and the last of them checks the global queue again
+    /// when it gives up, so no notification gets lost.
+    #[inline]
+    fn notify_unless_searching(&self) {
+        if self.searching_count.load(Ordering::SeqCst) == 0 {
+            self.notify();
+        }
+    }
+
+    /// Moves a runner into searching state if fewer than half of the runners are searching.
+    fn start_searching(&self) -> bool {
+        let max = (self.runner_count.load(Ordering::Relaxed) / 2).max(1);
+        self.searching_count
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                if n < max {
+                    Some(n + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
+
+/// A named group of tasks.
+#[derive(Debug)]
+struct Group {
+    /// The name of the group.
+    name: String,
+
+    /// The ID of the group in the fair queue.
+    id: usize,
+
+    /// Limit on live tasks in the group.
+    limit: TaskLimit<Pending>,
+
+    /// Wakers of unfinished tasks in the group.
+    members: Mutex<Slab<Waker>>,
+}
+
+impl Group {
+    /// Creates an empty group.
+    fn new(name: &str, id: usize) -> Group {
+        Group {
+            name: name.to_string(),
+            id,
+            limit: TaskLimit::new(),
+            members: Mutex::new(Slab::new()),
+        }
+    }
+}
+
+/// A task waiting for a slot.
+type Pending = (Runnable, Arc<Admission>);
+
+/// Set in [`Admission::held`] when the task holds a slot in its group.
+const GROUP_SLOT: u8 = 1 << 0;
+
+/// Set in [`Admission::held`] when the task holds a slot in the executor.
+const EXECUTOR_SLOT: u8 = 1 << 1;
+
+/// Tracks which slots a task holds.
+#[derive(Debug)]
+struct Admission {
+    /// The group the task belongs to.
+    group: Option<Arc<Group>>,
+
+    /// Slots held by the task.
+    held: AtomicU8,
+}
+
+impl Admission {
+    /// Creates an admission that holds no slots yet.
+    fn new(group: Option<Arc<Group>>) -> Admission {
+        Admission {
+            group,
+            held: AtomicU8::new(0),
+        }
+    }
 }
 
 /// A list of sleeping tickers.
+///
+/// All operations take constant time: sleeping tickers live in a slab indexed by their ID, and
+/// the unnotified ones are additionally linked into an intrusive stack that supports unlinking
+/// from the middle.
 #[derive(Debug)]
 struct Sleepers {
-    /// Number of sleeping tickers (both notified and unnotified).
-    count: usize,
+    /// Sleeping tickers (both notified and unnotified), keyed by their ID minus one.
+    entries: Slab<Sleeper>,
 
-    /// IDs and wakers of sleeping unnotified tickers.
-    ///
-    /// A sleeping ticker is notified when its waker is missing from this list.
-    wakers: Vec<(usize, Waker)>,
+    /// Number of sleeping unnotified tickers.
+    unnotified: usize,
+
+    /// The most recently inserted sleeping unnotified ticker.
+    head: Option<usize>,
+}
+
+/// An entry in the list of sleeping tickers.
+#[derive(Debug)]
+struct Sleeper {
+    /// The ticker's waker, or `None` if the ticker was notified.
+    waker: Option<Waker>,
+
+    /// The previous unnotified ticker in the stack, closer to the head.
+    prev: Option<usize>,
 
-    /// Reclaimed IDs.
-    free_ids: Vec<usize>,
+    /// The next unnotified ticker in the stack, further from the head.
+    next: Option<usize>,
 }
 
 impl Sleepers {
+    /// Creates an empty list.
+    fn new() -> Sleepers {
+        Sleepers {
+            entries: Slab::new(),
+            unnotified: 0,
+            head: None,
+        }
+    }
+
     /// Inserts a new sleeping ticker.
     fn insert(&mut self, waker: &Waker) -> usize {
-        let id = match self.free_ids.pop() {
-            Some(id) => id,
-            None => self.count + 1,
-        };
-        self.count += 1;
-        self.wakers.push((id, waker.clone()));
-        id
+        let key = self.entries.insert(Sleeper {
+            waker: None,
+            prev: None,
+            next: None,
+        });
+        self.push(key, waker.clone());
+        key + 1
     }
 
     /// Re-inserts a sleeping ticker's waker if it was notified.
     ///
     /// Returns `true` if the ticker was notified.
     fn update(&mut self, id: usize, waker: &Waker) -> bool {
-        for item in &mut self.wakers {
-            if item.0 == id {
-                if !item.1.will_wake(waker) {
-                    item.1 = waker.clone();
+        let key = id - 1;
+        match &mut self.entries[key].waker {
+            Some(w) => {
+                if !w.will_wake(waker) {
+                    *w = waker.clone();
                 }
-                return false;
+                false
+            }
+            None => {
+                self.push(key, waker.clone());
+                true
             }
         }
-
-        self.wakers.push((id, waker.clone()));
-        true
     }
 
     /// Removes a previously inserted sleeping ticker.
     ///
-    /// Returns `true` if the ticker was notified.
+    /// Returns the ticker's waker if it was not notified.
     fn remove(&mut self, id: usize) -> Option<Waker> {
-        self.count -= 1;
-        self.free_ids.push(id);
-
-        for i in (0..self.wakers.len()).rev() {
-            if self.wakers[i].0 == id {
-                let (_, waker) = self.wakers.remove(i);
-                return Some(waker);
-            }
+        let key = id - 1;
+        if self.entries[key].waker.is_some() {
+            self.unlink(key);
         }
-        None
+        self.entries.remove(key).waker
     }
 
     /// Returns `true` if a sleeping ticker is notified or no tickers are sleeping.
     fn is_notified(&self) -> bool {
-        self.count == 0 || self.count > self.wakers.len()
+        self.entries.is_empty() || self.entries.len() > self.unnotified
     }
 
     /// Returns notification waker for a sleeping ticker.
     ///
     /// If a ticker was notified already or there are no tickers, `None` will be returned.
     fn notify(&mut self) -> Option<Waker> {
-        if self.wakers.len() == self.count {
-            self.wakers.pop().map(|item| item.1)
+        if self.unnotified == self.entries.len() {
+            let key = self.head?;
+            self.unlink(key);
+            self.entries[key].waker.take()
         } else {
             None
         }
     }
+
+    /// Marks a ticker as unnotified by pushing it onto the stack.
+    fn push(&mut self, key: usize, waker: Waker) {
+        if let Some(head) = self.head {
+            self.entries[head].prev = Some(key);
+        }
+
+        let entry = &mut self.entries[key];
+        entry.waker = Some(waker);
+        entry.prev = None;
+        entry.next = self.head;
+
+        self.head = Some(key);
+        self.unnotified += 1;
+    }
+
+    /// Unlinks an unnotified ticker from the stack, leaving its waker in place.
+    fn unlink(&mut self, key: usize) {
+        let (prev, next) = {
+            let entry = &mut self.entries[key];
+            (entry.prev.take(), entry.next.take())
+        };
+
+        match prev {
+            Some(prev) => self.entries[prev].next = next,
+            None => self.head = next,
+        }
+        if let Some(next) = next {
+            self.entries[next].prev = prev;
+        }
+
+        self.unnotified -= 1;
+    }
 }
 
 /// Runs task one by one.
@@ -619,9 +1894,9 @@ struct Ticker {
     /// Set to a non-zero sleeper ID when in sleeping state.
     ///
     /// States a ticker can be in:
-    /// 1) Woken.
-    /// 2a) Sleeping and unnotified.
-    /// 2b) Sleeping and notified.
+    /// - 1) Woken.
+    /// - 2a) Sleeping and unnotified.
+    /// - 2b) Sleeping and notified.
     sleeping: AtomicUsize,
 }
 
@@ -679,35 +1954,40 @@ impl Ticker {
 
     /// Waits for the next runnable task to run.
     async fn runnable(&self) -> Runnable {
-        self.runnable_with(|| self.state.queue.pop()).await
+        self.runnable_with(|| {
+            let runnable = self.state.queue.pop().or_else(|| self.state.fair.pop());
+            if runnable.is_some() {
+                // Notify another ticker to pick up where this ticker left off.
+                self.state.notify_unless_searching();
+            }
+            runnable
+        })
+        .await
     }
 
     /// Waits for the next runnable task to run, given a function that searches for a task.
     async fn runnable_with(&self, mut search: impl FnMut() -> Option<Runnable>) -> Runnable {
+        let mut slept = false;
+
         future::poll_fn(|cx| {
             loop {
-                // This kills performance somehow
-                // DEBUG_SEARCHING_COUNT.fetch_add(1, Ordering::Relaxed);
-                let res = search();
-                // DEBUG_SEARCHING_COUNT.fetch_sub(1, Ordering::Relaxed);
-
-                match res {
+                match search() {
                     None => {
+                        slept = true;
+
                         // Move to sleeping and unnotified state.
                         if !self.sleep(cx.waker()) {
                             // If already sleeping and unnotified, return.
+                            event!("runner parked");
                             return Poll::Pending;
                         }
                     }
                     Some(r) => {
                         // Wake up.
-                        self.wake();
-                        // Sibling notification.
-                        if self.state.searching_count.load(Ordering::Relaxed) == 0
-                        // && fastrand::u8(0..) < 5
-                        {
-                            self.state.notify();
+                        if slept {
+                            event!("runner unparked");
                         }
+                        self.wake();
                         return Poll::Ready(r);
                     }
                 }
@@ -739,65 +2019,113 @@ impl Drop for Ticker {
     }
 }
 
+/// A runner's fast path for tasks woken on its thread.
 struct TlsData {
+    /// The executor state.
     state: Arc<State>,
+
+    /// The runner's ticker, which also identifies the runner.
     ticker: Arc<Ticker>,
-    pending_tasks: Vec<Runnable>,
+
+    /// Tasks woken on this thread, waiting to be moved into the runner's local queue.
+    ///
+    /// Registered in the executor state like local queues, so other runners can steal these
+    /// tasks while the runner is busy polling a task.
+    pending_tasks: LocalQueue,
+
+    /// The key of `pending_tasks` in the list of local queues.
+    id: usize,
+
+    /// The runner's latency histogram, if runners record their own latency.
+    latency: Option<Arc<AtomicHistogram>>,
 }
 
 impl Drop for TlsData {
     fn drop(&mut self) {
+        self.state.local_queues.write().remove(self.id);
+
         // move the pending tasks into the state
-        for task in self.pending_tasks.drain(0..) {
-            self.state.queue.push(task)
+        if !self.pending_tasks.is_empty() {
+            self.pending_tasks.flush(&self.state.queue);
+            self.state.notify_unless_searching();
         }
     }
 }
 
 thread_local! {
-    static TLS: RefCell<Option<TlsData>> = Default::default()
+    /// Runners active on this thread, innermost last.
+    ///
+    /// Runners get nested when a task blocks on another executor's `run()`, and several runners
+    /// are active at once when multiple `run()` futures are polled by the same thread.
+    static TLS: RefCell<Vec<TlsData>> = const { RefCell::new(Vec::new()) };
 }
 
-fn clear_tls() {
-    TLS.with(|v| *v.borrow_mut() = Default::default())
+/// Removes a runner's fast path from the TLS.
+fn leave_tls(ticker: &Arc<Ticker>) {
+    let data = TLS.with(|tls| {
+        let mut tls = tls.borrow_mut();
+        let index = tls.iter().rposition(|d| Arc::ptr_eq(&d.ticker, ticker))?;
+        Some(tls.remove(index))
+    });
+
+    // Drop outside the borrow since it may wake other tickers.
+    drop(data);
 }
 
-fn try_push_tls(state: &Arc<State>, runnable: Runnable) -> Result<(), Runnable> {
+/// Pushes a woken task into the fast path of the innermost runner of its executor on this thread.
+fn try_push_tls(state: &Arc<State>, runnable: Runnable, key: Key) -> Result<(), Runnable> {
     TLS.with(|tls| {
-        let tls = tls.try_borrow_mut();
-        if let Ok(mut tls) = tls {
-            if let Some(tlsdata) = tls.as_mut() {
-                if !Arc::ptr_eq(state, &tlsdata.state) {
-                    return Err(runnable);
-                }
-                tlsdata.pending_tasks.push(runnable);
-                // notify ticker
-                // eprintln!("successfully pushed locally");
-                if let Some(v) = tlsdata.ticker.wake() {
-                    v.wake()
-                }
-                Ok(())
-            } else {
-                Err(runnable)
-            }
-        } else {
-            Err(runnable)
+        let mut tls = match tls.try_borrow_mut() {
+            Ok(tls) => tls,
+            Err(_) => return Err(runnable),
+        };
+        let tlsdata = match tls.iter_mut().rev().find(|d| Arc::ptr_eq(state, &d.state)) {
+            Some(tlsdata) => tlsdata,
+            None => return Err(runnable),
+        };
+
+        // Some queues, such as the heap, never take the task, so it goes to the global queue.
+        let mut global = false;
+        if let Err(runnable) = tlsdata.pending_tasks.push(runnable, key) {
+            tlsdata.pending_tasks.spill(&state.queue);
+            state.queue.push(runnable, key);
+            global = true;
+        }
+
+        // If the runner is asleep, waking it up is enough. Otherwise it is busy polling a task
+        // and only gets to this one once that poll returns, so another runner should steal it.
+        // Tasks in the global queue are up for grabs by any runner either way.
+        let waker = tlsdata.ticker.wake();
+        drop(tls);
+        let busy = waker.is_none();
+        if let Some(waker) = waker {
+            waker.wake();
         }
+        if busy || global {
+            state.notify_unless_searching();
+        }
+        Ok(())
     })
 }
 
-fn try_pop_tls() -> Option<Vec<Runnable>> {
-    TLS.with(|tls| {
+/// Moves the tasks waiting in a runner's fast path on this thread into its local queue.
+fn drain_tls(ticker: &Arc<Ticker>, local: &mut LocalQueue) {
+    let spilled = TLS.with(|tls| {
         let mut tls = tls.borrow_mut();
-        if let Some(tlsdata) = tls.as_mut() {
-            Some(std::mem::replace(
-                &mut tlsdata.pending_tasks,
-                Vec::with_capacity(4),
-            ))
-        } else {
-            None
-        }
-    })
+        let tlsdata = tls
+            .iter_mut()
+            .rev()
+            .find(|d| Arc::ptr_eq(&d.ticker, ticker))?;
+
+        // If the local queue fills up, half of it is shared with the other runners.
+        let spilled = local.append(&mut tlsdata.pending_tasks, &tlsdata.state.queue);
+        spilled.then(|| tlsdata.state.clone())
+    });
+
+    // Notify outside the borrow since it may wake other tickers.
+    if let Some(state) = spilled {
+        state.notify_unless_searching();
+    }
 }
 
 /// A worker in a work-stealing executor.
@@ -819,6 +2147,15 @@ struct Runner {
 
     /// ID.
     id: usize,
+
+    /// The runner's latency histogram and its key in the executor state, if recorded.
+    latency: Option<(usize, Arc<AtomicHistogram>)>,
+
+    /// Tasks pinned to this runner.
+    pinned: Arc<Pinned>,
+
+    /// The worker ID, which is the key of `pinned` in the executor state.
+    worker: usize,
 }
 
 impl Runner {
@@ -827,28 +2164,45 @@ impl Runner {
         let mut runner = Runner {
             state: state.clone(),
             ticker: Arc::new(Ticker::new(state.clone())),
-            local: LocalQueue::default(),
+            local: state.queue.local(),
             ticks: 0,
             id: 0,
+            latency: None,
+            pinned: Arc::new(Pinned::new()),
+            worker: 0,
         };
         runner.id = state.local_queues.write().insert(runner.local.handle());
+        runner.worker = state.workers.write().insert(runner.pinned.clone());
+        if state.config.record_runner_latency {
+            let histogram = Arc::new(AtomicHistogram::new());
+            let key = state.runner_latency.lock().insert(histogram.clone());
+            runner.latency = Some((key, histogram));
+        }
+        state.runner_count.fetch_add(1, Ordering::Relaxed);
         runner
     }
 
-    /// Sets as active in the TLS
-    fn set_tls_active(&self) {
-        // let weak_ticker = Arc::downgrade(&self.ticker);
-        // let weak_local = Arc::downgrade(&self.local);
+    /// Registers the runner's fast path in the TLS until the returned guard is dropped.
+    fn enter_tls(&self) -> CallOnDrop<impl Fn()> {
+        let pending_tasks = self.state.queue.local();
+        let id = self
+            .state
+            .local_queues
+            .write()
+            .insert(pending_tasks.handle());
+
         TLS.with(|tls| {
-            let mut tls = tls.borrow_mut();
-            if tls.is_none() {
-                *tls = Some(TlsData {
-                    state: self.state.clone(),
-                    ticker: self.ticker.clone(),
-                    pending_tasks: Vec::new(),
-                })
-            }
-        })
+            tls.borrow_mut().push(TlsData {
+                state: self.state.clone(),
+                ticker: self.ticker.clone(),
+                pending_tasks,
+                id,
+                latency: self.latency.as_ref().map(|(_, h)| h.clone()),
+            })
+        });
+
+        let ticker = self.ticker.clone();
+        CallOnDrop(move || leave_tls(&ticker))
     }
 
     /// Waits for the next runnable task to run.
@@ -856,80 +2210,133 @@ impl Runner {
         // static USELESS_WAKEUP_COUNT: AtomicUsize = AtomicUsize::new(0);
         // static GOOD_WAKEUP_COUNT: AtomicUsize = AtomicUsize::new(0);
 
-        let runnable = self
-            .ticker
-            .clone()
-            .runnable_with(|| {
-                let must_yield = JUST_YIELDED.with(|v| v.replace(false));
-                // Try the TLS.
-                if let Some(r) = try_pop_tls() {
-                    for task in r {
-                        // SAFETY: only one thread can push to self.local at the same time
-                        if let Err(task) = self.local.push(must_yield, task) {
-                            self.state.queue.push(task);
-                        }
+        let ticker = self.ticker.clone();
+        let pinned = self.pinned.clone();
+        let runnable = {
+            let search = ticker.runnable_with(|| {
+                // Tasks of groups and pinned tasks take turns with other tasks.
+                if self.ticks.is_multiple_of(2) {
+                    if let Some(r) = self.state.fair.pop() {
+                        return Some(r);
                     }
+                } else if let Some(r) = self.pinned.pop() {
+                    return Some(r);
                 }
 
+                // Try the TLS.
+                drain_tls(&self.ticker, &mut self.local);
+
                 // Try the local queue.
                 if let Some(r) = self.local.pop() {
+                    // If more tasks are queued here, get another runner to steal some.
+                    if !self.local.is_empty() {
+                        self.state.notify_unless_searching();
+                    }
                     return Some(r);
                 }
 
-                self.state.searching_count.fetch_add(1, Ordering::Relaxed);
-                // Try stealing from the global queue.
+                // Try tasks of groups and pinned tasks even if it's not their turn.
+                if let Some(r) = self.state.fair.pop().or_else(|| self.pinned.pop()) {
+                    return Some(r);
+                }
+
+                // Try the global queue, which is cheap enough to check without searching.
                 self.local.steal_global(&self.state.queue);
                 if let Some(r) = self.local.pop() {
-                    self.state.searching_count.fetch_sub(1, Ordering::Relaxed);
+                    event!(runner = self.id, "stole tasks from the global queue");
                     return Some(r);
                 }
 
-                // Try stealing from other runners.
-                let local_queues = self.state.local_queues.read();
-
-                // Pick a random starting point in the iterator list and rotate the list.
-                let n = local_queues.len();
-                let start = fastrand::usize(..n);
-                let iter = local_queues
-                    .iter()
-                    .chain(local_queues.iter())
-                    .skip(start)
-                    .take(n);
-
-                // Remove this runner's local queue.
-                let id = self.id;
-                let iter = iter.filter(|local| local.0 != id);
-
-                // Try stealing from each local queue in the list.
-                for (_, local) in iter {
-                    self.local.steal_local(local);
-                    if let Some(r) = self.local.pop() {
-                        self.state.searching_count.fetch_sub(1, Ordering::Relaxed);
-                        return Some(r);
+                // Cap the number of searching runners so that a burst of tasks doesn't send every
+                // sleeping runner after the same queues.
+                if !self.state.start_searching() {
+                    return None;
+                }
+                let found = self.steal();
+
+                // The last searcher to find a task wakes up another runner to continue the search,
+                // which chains wake-ups for as long as tasks keep being found. The last searcher to
+                // give up checks the global queue once more, since tasks pushed while runners were
+                // searching didn't notify anyone.
+                let last = self.state.searching_count.fetch_sub(1, Ordering::SeqCst) == 1;
+                let found = match found {
+                    None if last => {
+                        self.local.steal_global(&self.state.queue);
+                        self.local.pop()
                     }
+                    found => found,
+                };
+                if last && found.is_some() {
+                    self.state.notify();
                 }
+                found
+            });
 
-                self.state.searching_count.fetch_sub(1, Ordering::Relaxed);
-                None
+            // Make sure pinned tasks wake up this runner while it's waiting.
+            futures_lite::pin!(search);
+            future::poll_fn(|cx| {
+                pinned.register(cx.waker());
+                search.as_mut().poll(cx)
             })
-            .await;
+            .await
+        };
 
         // Bump the tick counter.
         self.ticks += 1;
 
-        if self.ticks % 64 == 0 {
+        if self.ticks.is_multiple_of(64) {
             // Steal tasks from the global queue to ensure fair task scheduling.
             self.local.steal_global(&self.state.queue)
         }
 
         runnable
     }
+
+    /// Steals tasks from other runners and returns one of them.
+    fn steal(&mut self) -> Option<Runnable> {
+        let local_queues = self.state.local_queues.read();
+
+        // Pick a random starting point in the iterator list and rotate the list.
+        let n = local_queues.len();
+        let start = fastrand::usize(..n);
+        let iter = local_queues
+            .iter()
+            .chain(local_queues.iter())
+            .skip(start)
+            .take(n);
+
+        // Remove this runner's local queue.
+        let id = self.id;
+        let iter = iter.filter(|local| local.0 != id);
+
+        // Try stealing from each local queue in the list.
+        for (_, local) in iter {
+            self.local.steal_local(local);
+            if let Some(r) = self.local.pop() {
+                event!(runner = id, "stole tasks from another runner");
+                return Some(r);
+            }
+        }
+
+        None
+    }
 }
 
 impl Drop for Runner {
     fn drop(&mut self) {
+        // Cancel pinned tasks. They can only be dropped on this runner's thread, so leak them if
+        // the runner was moved to another thread.
+        self.state.workers.write().remove(self.worker);
+        if !self.pinned.close() {
+            mem::forget(self.pinned.clone());
+        }
+
         // Remove the local queue.
         self.state.local_queues.write().remove(self.id);
+        self.state.runner_count.fetch_sub(1, Ordering::Relaxed);
+        if let Some((key, _)) = self.latency {
+            self.state.runner_latency.lock().remove(key);
+        }
 
         // Re-schedule remaining tasks in the local queue.
         // SAFETY: this cannot possibly be run from two different threads concurrently.
@@ -938,6 +2345,52 @@ impl Drop for Runner {
         }
     }
 }
+
+/// Unparks a thread blocked in [`Executor::block_on()`] when woken.
+#[derive(Debug)]
+struct Unparker {
+    /// The blocked thread.
+    thread: Thread,
+
+    /// Set when woken, and cleared by the blocked thread.
+    notified: AtomicBool,
+}
+
+impl Unparker {
+    /// Creates an unparker for the current thread.
+    fn new() -> Unparker {
+        Unparker {
+            thread: thread::current(),
+            notified: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Wake for Unparker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        if !self.notified.swap(true, Ordering::SeqCst) {
+            self.thread.unpark();
+        }
+    }
+}
+
+/// Returns the ID of the current thread.
+fn thread_id() -> ThreadId {
+    thread_local! {
+        static ID: ThreadId = thread::current().id();
+    }
+    ID.with(|id| *id)
+}
+
+/// Returns the number of nanoseconds elapsed since `epoch`, saturating on overflow.
+fn nanos_since(epoch: Instant) -> u64 {
+    u64::try_from(epoch.elapsed().as_nanos()).unwrap_or(u64::MAX)
+}
+
 /// Runs a closure when dropped.
 struct CallOnDrop<F: Fn()>(F);
 
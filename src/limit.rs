@@ -0,0 +1,105 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use parking_lot::Mutex;
+
+/// A semaphore-like cap on the number of live tasks.
+///
+/// Entries that don't fit are queued in FIFO order and handed back once a slot frees up.
+#[derive(Debug)]
+pub(crate) struct TaskLimit<T> {
+    /// Maximum number of live tasks, or `usize::MAX` if unlimited.
+    max: AtomicUsize,
+
+    /// Number of tasks currently holding a slot.
+    live: AtomicUsize,
+
+    /// Number of entries waiting in `pending`.
+    queued: AtomicUsize,
+
+    /// Entries waiting for a free slot.
+    pending: Mutex<VecDeque<T>>,
+}
+
+impl<T> TaskLimit<T> {
+    /// Creates an unlimited task limit.
+    pub(crate) fn new() -> TaskLimit<T> {
+        TaskLimit {
+            max: AtomicUsize::new(usize::MAX),
+            live: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            pending: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Changes the maximum and returns the entries that got a slot as a result.
+    pub(crate) fn set_max(&self, max: Option<usize>) -> Vec<T> {
+        self.max.store(max.unwrap_or(usize::MAX), Ordering::SeqCst);
+        self.admit_pending()
+    }
+
+    /// Tries to take a slot for `entry`.
+    ///
+    /// Returns the entry back if it got a slot, or `None` if it was queued.
+    pub(crate) fn acquire(&self, entry: T) -> Option<T> {
+        // Don't overtake entries that are already waiting.
+        if self.queued.load(Ordering::SeqCst) == 0 && self.try_increment() {
+            return Some(entry);
+        }
+
+        let mut pending = self.pending.lock();
+
+        // Announce the entry before checking again so that a concurrent `release()` either
+        // frees a slot we can see or notices the queued entry.
+        self.queued.fetch_add(1, Ordering::SeqCst);
+        if pending.is_empty() && self.try_increment() {
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+            return Some(entry);
+        }
+
+        pending.push_back(entry);
+        None
+    }
+
+    /// Gives back a slot and returns the entries that got a slot as a result.
+    pub(crate) fn release(&self) -> Vec<T> {
+        self.live.fetch_sub(1, Ordering::SeqCst);
+        self.admit_pending()
+    }
+
+    /// Removes all queued entries without giving them a slot.
+    pub(crate) fn take_pending(&self) -> Vec<T> {
+        let mut pending = self.pending.lock();
+        self.queued.fetch_sub(pending.len(), Ordering::SeqCst);
+        pending.drain(..).collect()
+    }
+
+    /// Hands out free slots to queued entries.
+    fn admit_pending(&self) -> Vec<T> {
+        if self.queued.load(Ordering::SeqCst) == 0 {
+            return Vec::new();
+        }
+
+        let mut pending = self.pending.lock();
+        let mut admitted = Vec::new();
+        while !pending.is_empty() && self.try_increment() {
+            admitted.extend(pending.pop_front());
+            self.queued.fetch_sub(1, Ordering::SeqCst);
+        }
+        admitted
+    }
+
+    /// Takes a slot if one is free.
+    fn try_increment(&self) -> bool {
+        let max = self.max.load(Ordering::SeqCst);
+        self.live
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |live| {
+                if live < max {
+                    Some(live + 1)
+                } else {
+                    None
+                }
+            })
+            .is_ok()
+    }
+}
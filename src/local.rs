@@ -0,0 +1,494 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::panic::{RefUnwindSafe, UnwindSafe};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Poll, Waker};
+use std::thread::ThreadId;
+
+use async_task::{Runnable, Task};
+use concurrent_queue::ConcurrentQueue;
+use futures_lite::{future, prelude::*};
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::pinned::Worker;
+use crate::{thread_id, CallOnDrop};
+
+/// A thread-local executor.
+///
+/// The executor can only be run on the thread that created it. Unlike [`Executor`], it doesn't
+/// synchronize with other threads except when they wake its tasks.
+///
+/// [`Executor`]: crate::Executor
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::LocalExecutor;
+/// use futures_lite::future;
+///
+/// let local_ex = LocalExecutor::new();
+///
+/// future::block_on(local_ex.run(async {
+///     println!("Hello world!");
+/// }));
+/// ```
+#[derive(Debug)]
+pub struct LocalExecutor<'a> {
+    /// The executor state.
+    state: once_cell::unsync::OnceCell<Rc<State>>,
+
+    /// Makes the type `!Send` and `!Sync`, and invariant over `'a`.
+    _marker: PhantomData<(Rc<()>, std::cell::UnsafeCell<&'a ()>)>,
+}
+
+impl UnwindSafe for LocalExecutor<'_> {}
+impl RefUnwindSafe for LocalExecutor<'_> {}
+
+impl<'a> LocalExecutor<'a> {
+    /// Creates a single-threaded executor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    ///
+    /// let local_ex = LocalExecutor::new();
+    /// ```
+    pub const fn new() -> LocalExecutor<'a> {
+        LocalExecutor {
+            state: once_cell::unsync::OnceCell::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns `true` if there are no unfinished tasks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    ///
+    /// let local_ex = LocalExecutor::new();
+    /// assert!(local_ex.is_empty());
+    ///
+    /// let task = local_ex.spawn(async {
+    ///     println!("Hello world");
+    /// });
+    /// assert!(!local_ex.is_empty());
+    ///
+    /// assert!(local_ex.try_tick());
+    /// assert!(local_ex.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.state().active.borrow().is_empty()
+    }
+
+    /// Spawns a task onto the executor.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    ///
+    /// let local_ex = LocalExecutor::new();
+    ///
+    /// let task = local_ex.spawn(async {
+    ///     println!("Hello world");
+    /// });
+    /// ```
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub fn spawn<T: 'a>(&self, future: impl Future<Output = T> + 'a) -> Task<T> {
+        // SAFETY: All tasks are cancelled when the executor is dropped, before `'a` ends.
+        unsafe { self.state().spawn(future) }
+    }
+
+    /// Attempts to run a task if at least one is scheduled.
+    ///
+    /// Running a scheduled task means simply polling its future once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    ///
+    /// let ex = LocalExecutor::new();
+    /// assert!(!ex.try_tick()); // no tasks to run
+    ///
+    /// let task = ex.spawn(async {
+    ///     println!("Hello world");
+    /// });
+    /// assert!(ex.try_tick()); // a task was found
+    /// ```
+    pub fn try_tick(&self) -> bool {
+        let state = self.state();
+        match state.shared.pop() {
+            None => false,
+            Some(runnable) => {
+                state.run(runnable);
+                true
+            }
+        }
+    }
+
+    /// Runs a single task.
+    ///
+    /// Running a task means simply polling its future once.
+    ///
+    /// If no tasks are scheduled when this method is called, it will wait until one is scheduled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    /// use futures_lite::future;
+    ///
+    /// let ex = LocalExecutor::new();
+    ///
+    /// let task = ex.spawn(async {
+    ///     println!("Hello world");
+    /// });
+    /// future::block_on(ex.tick()); // runs the task
+    /// ```
+    pub async fn tick(&self) {
+        let runnable = self.runnable().await;
+        self.state().run(runnable);
+    }
+
+    /// Runs the executor until the given future completes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::LocalExecutor;
+    /// use futures_lite::future;
+    ///
+    /// let local_ex = LocalExecutor::new();
+    ///
+    /// let task = local_ex.spawn(async { 1 + 2 });
+    /// let res = future::block_on(local_ex.run(async { task.await * 2 }));
+    ///
+    /// assert_eq!(res, 6);
+    /// ```
+    pub async fn run<T>(&self, future: impl Future<Output = T>) -> T {
+        // A future that runs tasks forever.
+        let run_forever = async {
+            loop {
+                for _ in 0..200 {
+                    self.tick().await;
+                }
+                future::yield_now().await;
+            }
+        };
+
+        // Run `future` and `run_forever` concurrently until `future` completes.
+        future.or(run_forever).await
+    }
+
+    /// Waits for the next runnable task.
+    async fn runnable(&self) -> Runnable {
+        let shared = &self.state().shared;
+        future::poll_fn(|cx| {
+            if let Some(runnable) = shared.pop() {
+                return Poll::Ready(runnable);
+            }
+
+            // Check again after registering, in case a task got scheduled in the meantime.
+            shared.sleep(cx.waker());
+            match shared.pop() {
+                Some(runnable) => Poll::Ready(runnable),
+                None => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Returns a reference to the inner state.
+    fn state(&self) -> &Rc<State> {
+        self.state.get_or_init(|| Rc::new(State::new()))
+    }
+}
+
+impl Drop for LocalExecutor<'_> {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.get() {
+            let wakers = mem::take(&mut *state.active.borrow_mut());
+            for (_, w) in wakers {
+                w.wake();
+            }
+
+            // Dropping a scheduled task cancels it, and may wake other tasks, so repeat until
+            // everything is gone.
+            while let Some(runnable) = state.shared.pop() {
+                drop(runnable);
+            }
+        }
+    }
+}
+
+impl<'a> Default for LocalExecutor<'a> {
+    fn default() -> LocalExecutor<'a> {
+        LocalExecutor::new()
+    }
+}
+
+/// Spawns a task onto the local executor that is running a task on the current thread.
+///
+/// This makes a [`LocalExecutor`] reachable from its tasks without passing a reference to it
+/// around. The future doesn't need to be `Send`, but it can't borrow anything.
+///
+/// Inside a task spawned with [`Executor::spawn_pinned()`], this spawns another task pinned to
+/// the same worker instead.
+///
+/// [`Executor::spawn_pinned()`]: crate::Executor::spawn_pinned
+///
+/// # Panics
+///
+/// Panics if not called from inside a task of a [`LocalExecutor`] or a pinned task.
+///
+/// # Examples
+///
+/// ```
+/// use std::rc::Rc;
+///
+/// use async_executor::{spawn_local, LocalExecutor};
+/// use futures_lite::future;
+///
+/// let ex = LocalExecutor::new();
+///
+/// let task = ex.spawn(async {
+///     let n = Rc::new(1);
+///     spawn_local(async move { *n + 2 }).await
+/// });
+/// assert_eq!(future::block_on(ex.run(task)), 3);
+/// ```
+#[cfg_attr(feature = "tracing", track_caller)]
+pub fn spawn_local<T: 'static>(future: impl Future<Output = T> + 'static) -> Task<T> {
+    match CURRENT.with(Cell::get) {
+        // SAFETY: The executor is running a task on this thread, so it is alive, and the future
+        // doesn't borrow anything.
+        Current::Local(state) => unsafe { (*state).spawn(future) },
+        // SAFETY: The worker is running a task on this thread, so it is alive.
+        Current::Worker(worker) => unsafe { (*worker).spawn(future) },
+        Current::None => {
+            panic!(
+                "`spawn_local()` must be called from a task of a `LocalExecutor` or a pinned task"
+            )
+        }
+    }
+}
+
+thread_local! {
+    /// What is running a task on this thread.
+    static CURRENT: Cell<Current> = const { Cell::new(Current::None) };
+}
+
+/// What is running a task on the current thread, for [`spawn_local()`].
+#[derive(Clone, Copy)]
+enum Current {
+    /// No local executor or worker.
+    None,
+
+    /// A local executor.
+    Local(*const Rc<State>),
+
+    /// A worker running one of its pinned tasks.
+    Worker(*const Worker),
+}
+
+/// Makes `worker` the current one on this thread until the returned guard is dropped.
+pub(crate) fn enter_worker(worker: &Worker) -> CallOnDrop<impl Fn()> {
+    let prev = CURRENT.with(|current| current.replace(Current::Worker(worker)));
+    CallOnDrop(move || CURRENT.with(|current| current.set(prev)))
+}
+
+/// The state of a local executor.
+#[derive(Debug)]
+struct State {
+    /// Wakers of active tasks.
+    active: RefCell<Slab<Waker>>,
+
+    /// The ID of the next spawned task.
+    #[cfg(feature = "tracing")]
+    next_task_id: Cell<u64>,
+
+    /// The part of the state shared with wakers.
+    shared: Arc<Shared>,
+}
+
+impl State {
+    /// Creates state for a new executor on the current thread.
+    fn new() -> State {
+        State {
+            active: RefCell::new(Slab::new()),
+            #[cfg(feature = "tracing")]
+            next_task_id: Cell::new(0),
+            shared: Arc::new(Shared {
+                queue: OwnerQueue::new(),
+                inbox: ConcurrentQueue::unbounded(),
+                sleeping: AtomicBool::new(false),
+                wakers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Spawns a task onto the executor.
+    ///
+    /// # Safety
+    ///
+    /// The executor must be dropped before anything `future` borrows.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    unsafe fn spawn<T>(self: &Rc<Self>, future: impl Future<Output = T>) -> Task<T> {
+        // Run the task in its own span, a child of the current one.
+        #[cfg(feature = "tracing")]
+        let future = {
+            let id = self.next_task_id.get();
+            self.next_task_id.set(id + 1);
+            let span = crate::trace::task_span(id, None, std::panic::Location::caller());
+            crate::trace::instrument(span, future)
+        };
+
+        let mut active = self.active.borrow_mut();
+        let index = active.vacant_entry().key();
+
+        // Remove the task from the set of active tasks when the future is dropped.
+        let future = {
+            let state = self.clone();
+            async move {
+                let _guard = CallOnDrop(move || drop(state.active.borrow_mut().try_remove(index)));
+                future.await
+            }
+        };
+
+        let schedule = {
+            let shared = self.shared.clone();
+            move |runnable| shared.schedule(runnable)
+        };
+
+        // The future may not be `Send`, but tasks only ever run on this thread.
+        let (runnable, task) = async_task::spawn_unchecked(future, schedule);
+        active.insert(runnable.waker());
+        drop(active);
+
+        runnable.schedule();
+        task
+    }
+
+    /// Runs a task, with this executor as the current one on this thread.
+    fn run(self: &Rc<Self>, runnable: Runnable) {
+        let prev = CURRENT.with(|current| current.replace(Current::Local(self)));
+        let _guard = CallOnDrop(|| CURRENT.with(|current| current.set(prev)));
+        runnable.run();
+    }
+}
+
+/// The part of the state of a local executor that wakers hold on to.
+#[derive(Debug)]
+struct Shared {
+    /// Tasks scheduled on the executor's thread.
+    queue: OwnerQueue,
+
+    /// Tasks scheduled on other threads.
+    inbox: ConcurrentQueue<Runnable>,
+
+    /// Set when a ticker is waiting for a task.
+    sleeping: AtomicBool,
+
+    /// Wakers of waiting tickers.
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl Shared {
+    /// Schedules a task, which may have been woken on any thread.
+    fn schedule(&self, runnable: Runnable) {
+        if let Err(runnable) = self.queue.push(runnable) {
+            self.inbox.push(runnable).unwrap();
+        }
+        self.notify();
+    }
+
+    /// Pops the next scheduled task.
+    ///
+    /// Returns `None` if not called on the owner thread.
+    fn pop(&self) -> Option<Runnable> {
+        self.queue.pop(&self.inbox)
+    }
+
+    /// Registers a waker to be woken when a task gets scheduled.
+    fn sleep(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock();
+        if !wakers.iter().any(|w| w.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+        self.sleeping.store(true, Ordering::SeqCst);
+    }
+
+    /// Wakes waiting tickers, if any.
+    fn notify(&self) {
+        if self.sleeping.swap(false, Ordering::SeqCst) {
+            let wakers = mem::take(&mut *self.wakers.lock());
+            for waker in wakers {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// A queue of tasks that only the thread the executor belongs to can access.
+#[derive(Debug)]
+struct OwnerQueue {
+    /// The thread the executor belongs to.
+    owner: ThreadId,
+
+    /// Scheduled tasks.
+    queue: RefCell<VecDeque<Runnable>>,
+}
+
+// SAFETY: `queue` is only accessed by `push()` and `pop()`, which do nothing unless called on
+// the owner thread. It is never accessed from more than one thread, let alone concurrently.
+unsafe impl Sync for OwnerQueue {}
+
+// SAFETY: The runnables in the queue may belong to futures that are not `Send`, so they must not
+// be dropped on another thread, which only dropping the queue could do. Every runnable in the
+// queue belongs to a task of the executor, whose schedule function holds an `Arc<Shared>`. So
+// `Shared` and the queue in it stay alive as long as the queue holds runnables, and once the
+// last `Arc<Shared>` is dropped, on whatever thread, the queue is empty.
+unsafe impl Send for OwnerQueue {}
+
+impl OwnerQueue {
+    /// Creates an empty queue owned by the current thread.
+    fn new() -> OwnerQueue {
+        OwnerQueue {
+            owner: thread_id(),
+            queue: RefCell::new(VecDeque::new()),
+        }
+    }
+
+    /// Pushes a task, or returns it back if not called on the owner thread.
+    fn push(&self, runnable: Runnable) -> Result<(), Runnable> {
+        if thread_id() != self.owner {
+            return Err(runnable);
+        }
+        self.queue.borrow_mut().push_back(runnable);
+        Ok(())
+    }
+
+    /// Pops the next task after moving the tasks in `inbox` to the back of the queue.
+    ///
+    /// Returns `None` if not called on the owner thread.
+    fn pop(&self, inbox: &ConcurrentQueue<Runnable>) -> Option<Runnable> {
+        if thread_id() != self.owner {
+            return None;
+        }
+        let mut queue = self.queue.borrow_mut();
+        while let Ok(runnable) = inbox.pop() {
+            queue.push_back(runnable);
+        }
+        queue.pop_front()
+    }
+}
@@ -0,0 +1,241 @@
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::task::{Poll, Waker};
+use std::thread::{self, ThreadId};
+
+use async_task::{Runnable, Task};
+use concurrent_queue::ConcurrentQueue;
+use futures_lite::future;
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::{local, thread_id, CallOnDrop, State};
+
+/// The worker accepts and runs tasks.
+const OPEN: u8 = 0;
+
+/// The worker is cancelling its tasks.
+const CLOSING: u8 = 1;
+
+/// The worker has cancelled all of its tasks.
+///
+/// Tasks that get scheduled from now on have never been polled, so they can be dropped anywhere.
+const CLOSED: u8 = 2;
+
+/// Tasks pinned to one runner, which only ever runs them on its own thread.
+#[derive(Debug)]
+pub(crate) struct Pinned {
+    /// The thread the runner was created on.
+    owner: ThreadId,
+
+    /// One of `OPEN`, `CLOSING` and `CLOSED`.
+    state: AtomicU8,
+
+    /// Scheduled tasks.
+    queue: ConcurrentQueue<Runnable>,
+
+    /// Wakers of tasks that have been polled, and whose futures must be dropped on the owner
+    /// thread.
+    started: Mutex<Slab<Waker>>,
+
+    /// The waker of the runner, woken when a task gets scheduled.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Pinned {
+    /// Creates an empty set of tasks for a runner on the current thread.
+    pub(crate) fn new() -> Pinned {
+        Pinned {
+            owner: thread_id(),
+            state: AtomicU8::new(OPEN),
+            queue: ConcurrentQueue::unbounded(),
+            started: Mutex::new(Slab::new()),
+            waker: Mutex::new(None),
+        }
+    }
+
+    /// Creates a set of tasks that drops every task scheduled onto it.
+    pub(crate) fn closed() -> Pinned {
+        let pinned = Pinned::new();
+        pinned.state.store(CLOSED, Ordering::SeqCst);
+        pinned
+    }
+
+    /// Returns a future that creates a future on the owner thread and runs it.
+    ///
+    /// The future is run with a [`Worker`] as the current one, so that [`spawn_local()`] spawns
+    /// more tasks onto this runner. The returned future must only be polled on the owner thread.
+    ///
+    /// [`spawn_local()`]: crate::spawn_local
+    pub(crate) fn wrap<F, Fut>(
+        self: &Arc<Self>,
+        state: Arc<State>,
+        factory: F,
+    ) -> impl Future<Output = Fut::Output>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future,
+    {
+        let worker = Worker {
+            state,
+            pinned: self.clone(),
+        };
+        async move {
+            // Register the task before its future exists so that the runner can cancel it.
+            let pinned = &worker.pinned;
+            let key =
+                future::poll_fn(|cx| Poll::Ready(pinned.started.lock().insert(cx.waker().clone())))
+                    .await;
+            let _guard = CallOnDrop(move || drop(pinned.started.lock().try_remove(key)));
+
+            let future = factory();
+            futures_lite::pin!(future);
+            future::poll_fn(|cx| {
+                let _current = local::enter_worker(&worker);
+                future.as_mut().poll(cx)
+            })
+            .await
+        }
+    }
+
+    /// Schedules a task, which may have been woken on any thread.
+    pub(crate) fn schedule(&self, runnable: Runnable) {
+        if self.state.load(Ordering::SeqCst) == CLOSED {
+            drop(runnable);
+            return;
+        }
+        self.queue.push(runnable).unwrap();
+
+        // If the runner finished closing in the meantime, nobody else is going to drop the task.
+        if self.state.load(Ordering::SeqCst) == CLOSED {
+            while let Ok(runnable) = self.queue.pop() {
+                drop(runnable);
+            }
+            return;
+        }
+
+        if let Some(waker) = &*self.waker.lock() {
+            waker.wake_by_ref();
+        }
+    }
+
+    /// Pops a scheduled task if called on the owner thread.
+    pub(crate) fn pop(&self) -> Option<Runnable> {
+        if thread_id() != self.owner {
+            return None;
+        }
+        self.queue.pop().ok()
+    }
+
+    /// Registers the runner's waker.
+    pub(crate) fn register(&self, waker: &Waker) {
+        let mut current = self.waker.lock();
+        if !current.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            *current = Some(waker.clone());
+        }
+    }
+
+    /// Cancels all tasks, dropping their futures if called on the owner thread.
+    ///
+    /// Returns `false` if called on another thread, in which case tasks that have been polled
+    /// must be leaked.
+    pub(crate) fn close(&self) -> bool {
+        if thread_id() != self.owner {
+            return false;
+        }
+        self.state.store(CLOSING, Ordering::SeqCst);
+
+        // Schedule every started task and drop it, which may wake other tasks, so repeat until
+        // they're all gone.
+        let wakers: Vec<Waker> = self.started.lock().iter().map(|(_, w)| w.clone()).collect();
+        for waker in wakers {
+            waker.wake();
+        }
+        loop {
+            while let Ok(runnable) = self.queue.pop() {
+                drop(runnable);
+            }
+            if self.started.lock().is_empty() {
+                break;
+            }
+            // A task is being scheduled on another thread right now.
+            thread::yield_now();
+        }
+
+        self.state.store(CLOSED, Ordering::SeqCst);
+        while let Ok(runnable) = self.queue.pop() {
+            drop(runnable);
+        }
+        true
+    }
+}
+
+/// A runner running one of its pinned tasks.
+#[derive(Debug)]
+pub(crate) struct Worker {
+    /// The state of the executor.
+    state: Arc<State>,
+
+    /// Tasks pinned to the runner.
+    pinned: Arc<Pinned>,
+}
+
+impl Worker {
+    /// Spawns a task pinned to this runner.
+    ///
+    /// Must be called on the runner's thread.
+    #[cfg_attr(feature = "tracing", track_caller)]
+    pub(crate) fn spawn<T>(&self, future: impl Future<Output = T> + 'static) -> Task<T> {
+        // The future already exists, so make sure it is never dropped on another thread.
+        let future = OwnerOnly::new(future);
+        let future = self
+            .pinned
+            .wrap(self.state.clone(), move || future.into_inner());
+        let schedule = {
+            let pinned = self.pinned.clone();
+            move |runnable| pinned.schedule(runnable)
+        };
+
+        let id = self.state.next_task_id.fetch_add(1, Ordering::Relaxed);
+        // SAFETY: The future is only polled and dropped on the runner's thread, and it doesn't
+        // borrow anything.
+        unsafe { self.state.spawn(id, future, None, schedule) }
+    }
+}
+
+/// A value that is leaked instead of dropped on threads other than the one that created it.
+struct OwnerOnly<T> {
+    /// The thread that created the value.
+    owner: ThreadId,
+
+    /// The value.
+    value: ManuallyDrop<T>,
+}
+
+impl<T> OwnerOnly<T> {
+    /// Wraps a value created on the current thread.
+    fn new(value: T) -> OwnerOnly<T> {
+        OwnerOnly {
+            owner: thread_id(),
+            value: ManuallyDrop::new(value),
+        }
+    }
+
+    /// Unwraps the value.
+    fn into_inner(self) -> T {
+        let mut this = ManuallyDrop::new(self);
+        // SAFETY: `this` is never dropped, so the value is only taken once.
+        unsafe { ManuallyDrop::take(&mut this.value) }
+    }
+}
+
+impl<T> Drop for OwnerOnly<T> {
+    fn drop(&mut self) {
+        if thread_id() == self.owner {
+            // SAFETY: The value is dropped only once, here.
+            unsafe { ManuallyDrop::drop(&mut self.value) }
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::Waker;
+
+use async_task::{Runnable, Task};
+use crossbeam_utils::CachePadded;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use slab::Slab;
+
+/// Number of shards in a registry, a power of two scaled to the number of CPUs.
+static SHARDS: Lazy<usize> = Lazy::new(|| {
+    let cpus = std::thread::available_parallelism().map_or(1, |n| n.get());
+    cpus.next_power_of_two().min(64)
+});
+
+/// The set of unfinished tasks.
+#[derive(Debug)]
+pub(crate) enum Registry {
+    /// Wakers of active tasks, split into independently locked shards.
+    ///
+    /// Spawning and completing a task only locks one shard, so threads rarely contend.
+    Tracked(Box<[CachePadded<Mutex<Slab<Waker>>>]>),
+
+    /// Only the number of unfinished tasks.
+    Counted(CachePadded<AtomicUsize>),
+}
+
+impl Registry {
+    /// Creates an empty registry.
+    ///
+    /// If `track` is `false`, tasks are only counted and their wakers are not kept.
+    pub(crate) fn new(track: bool) -> Registry {
+        if track {
+            Registry::Tracked(
+                (0..*SHARDS)
+                    .map(|_| CachePadded::new(Mutex::new(Slab::new())))
+                    .collect(),
+            )
+        } else {
+            Registry::Counted(CachePadded::new(AtomicUsize::new(0)))
+        }
+    }
+
+    /// Returns `true` if the wakers of unfinished tasks are kept.
+    pub(crate) fn is_tracked(&self) -> bool {
+        matches!(self, Registry::Tracked(_))
+    }
+
+    /// Registers a task.
+    ///
+    /// The closure receives the task's key and creates the task. When tracking, a shard stays
+    /// locked while the closure runs.
+    pub(crate) fn insert<T>(
+        &self,
+        f: impl FnOnce(usize) -> (Runnable, Task<T>),
+    ) -> (Runnable, Task<T>) {
+        match self {
+            Registry::Tracked(shards) => {
+                let shard = fastrand::usize(..shards.len());
+                let mut slab = shards[shard].lock();
+
+                let key = slab.vacant_entry().key() * shards.len() + shard;
+                let (runnable, task) = f(key);
+                slab.insert(runnable.waker());
+                (runnable, task)
+            }
+            Registry::Counted(count) => {
+                count.fetch_add(1, Ordering::SeqCst);
+                f(0)
+            }
+        }
+    }
+
+    /// Unregisters a task, if it's still registered.
+    pub(crate) fn remove(&self, key: usize) {
+        match self {
+            Registry::Tracked(shards) => {
+                let shard = key % shards.len();
+                let waker = shards[shard].lock().try_remove(key / shards.len());
+                drop(waker);
+            }
+            Registry::Counted(count) => {
+                count.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Returns `true` if no tasks are registered.
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Registry::Tracked(shards) => shards.iter().all(|slab| slab.lock().is_empty()),
+            Registry::Counted(count) => count.load(Ordering::SeqCst) == 0,
+        }
+    }
+
+    /// Unregisters all tracked tasks and returns their wakers.
+    pub(crate) fn drain(&self) -> Vec<Waker> {
+        let mut wakers = Vec::new();
+        if let Registry::Tracked(shards) = self {
+            for slab in shards.iter() {
+                wakers.extend(slab.lock().drain());
+            }
+        }
+        wakers
+    }
+}
@@ -0,0 +1,482 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Poll, Waker};
+use std::time::{Duration, Instant};
+
+use futures_lite::future;
+use parking_lot::Mutex;
+
+use crate::timer::Sleep;
+use crate::{Executor, TaskFailure};
+
+/// A future returned by a child's factory, with its error boxed.
+type BoxedChild<'a> =
+    Pin<Box<dyn Future<Output = Result<(), Box<dyn Error + Send + Sync>>> + Send + 'a>>;
+
+/// A running child of a supervisor.
+type Running<'b> = Pin<Box<dyn Future<Output = Result<(), TaskFailure>> + Send + 'b>>;
+
+/// Which children a supervisor restarts when one of them fails.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{RestartStrategy, Supervisor};
+///
+/// let sup = Supervisor::new(RestartStrategy::RestForOne);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartStrategy {
+    /// Restart only the child that failed.
+    OneForOne,
+
+    /// Cancel all other children and restart all of them.
+    OneForAll,
+
+    /// Cancel the children added after the one that failed and restart all of those.
+    RestForOne,
+}
+
+/// Spawns tasks onto an executor and restarts them when they fail.
+///
+/// Children are added in order, either as factories that create the future of a task or as
+/// nested supervisors, and started in that order by [`Supervisor::run()`]. A child that returns
+/// an error or panics is restarted together with the other children chosen by the
+/// [`RestartStrategy`], after a backoff delay. Children that complete successfully are not
+/// restarted, and the supervisor completes once all of them have.
+///
+/// If children fail more often than [`Supervisor::max_restarts()`] allows, the supervisor cancels
+/// all of its children and returns the last failure. A nested supervisor that gives up counts as
+/// a failed child of its parent.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+/// use std::time::Duration;
+///
+/// use async_executor::{Executor, RestartStrategy, Supervisor};
+///
+/// let attempts = AtomicUsize::new(0);
+/// let ex = Executor::new();
+///
+/// let sup = Supervisor::new(RestartStrategy::OneForOne)
+///     .max_restarts(3, Duration::from_secs(5))
+///     .child(|| async {
+///         // Fails twice, then succeeds.
+///         match attempts.fetch_add(1, Ordering::SeqCst) {
+///             0 | 1 => Err("not yet"),
+///             _ => Ok(()),
+///         }
+///     });
+///
+/// ex.block_on(sup.run(&ex)).unwrap();
+/// assert_eq!(attempts.load(Ordering::SeqCst), 3);
+/// ```
+///
+/// A supervisor can run in the background on an executor stored in a `static`:
+///
+/// ```
+/// use async_executor::{Executor, RestartStrategy, Supervisor};
+///
+/// static EX: Executor<'_> = Executor::new();
+///
+/// let sup = Supervisor::new(RestartStrategy::OneForAll)
+///     .child(|| async { Ok::<(), String>(()) })
+///     .child(|| async { Ok::<(), String>(()) });
+///
+/// let task = EX.spawn(async move { sup.run(&EX).await });
+/// EX.block_on(task).unwrap();
+/// ```
+pub struct Supervisor<'a> {
+    strategy: RestartStrategy,
+
+    /// Maximum number of restarts within `window`.
+    max_restarts: usize,
+    window: Duration,
+
+    /// The delay before the first restart and the maximum delay.
+    backoff: (Duration, Duration),
+
+    children: Vec<Child<'a>>,
+    cancel: Arc<Cancel>,
+}
+
+impl<'a> Supervisor<'a> {
+    /// Creates a supervisor without children.
+    ///
+    /// By default, it allows 3 restarts within 5 seconds and restarts children right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne);
+    /// ```
+    pub fn new(strategy: RestartStrategy) -> Supervisor<'a> {
+        Supervisor {
+            strategy,
+            max_restarts: 3,
+            window: Duration::from_secs(5),
+            backoff: (Duration::ZERO, Duration::ZERO),
+            children: Vec::new(),
+            cancel: Arc::new(Cancel::default()),
+        }
+    }
+
+    /// Sets how many restarts are allowed within a window of time before giving up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne)
+    ///     .max_restarts(10, Duration::from_secs(60));
+    /// ```
+    pub fn max_restarts(mut self, max: usize, window: Duration) -> Supervisor<'a> {
+        self.max_restarts = max;
+        self.window = window;
+        self
+    }
+
+    /// Sets how long to wait before restarting failed children.
+    ///
+    /// The delay starts at `initial` and doubles with every restart within the
+    /// [restart window][Supervisor::max_restarts()], up to `max`.
+    ///
+    /// Delays are timed by the [timer](crate#timers) of the executor the supervisor runs on.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::time::Duration;
+    ///
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne)
+    ///     .backoff(Duration::from_millis(10), Duration::from_secs(1));
+    /// ```
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Supervisor<'a> {
+        self.backoff = (initial, max);
+        self
+    }
+
+    /// Adds a child that runs the future created by `factory` as a task.
+    ///
+    /// The factory is called again every time the child gets restarted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne).child(|| async {
+    ///     println!("Hello world");
+    ///     Ok::<(), std::io::Error>(())
+    /// });
+    /// ```
+    pub fn child<F, Fut, E>(mut self, factory: F) -> Supervisor<'a>
+    where
+        F: Fn() -> Fut + Send + Sync + 'a,
+        Fut: Future<Output = Result<(), E>> + Send + 'a,
+        E: Into<Box<dyn Error + Send + Sync>> + 'a,
+    {
+        self.children.push(Child::Task(Box::new(move || {
+            let future = factory();
+            Box::pin(async move { future.await.map_err(Into::into) })
+        })));
+        self
+    }
+
+    /// Adds a nested supervisor as a child.
+    ///
+    /// The nested supervisor runs as part of this one: it gets restarted when it gives up, and
+    /// its children get cancelled when this supervisor cancels it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let workers = Supervisor::new(RestartStrategy::OneForOne)
+    ///     .child(|| async { Ok::<(), String>(()) })
+    ///     .child(|| async { Ok::<(), String>(()) });
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::RestForOne)
+    ///     .child(|| async { Ok::<(), String>(()) })
+    ///     .supervisor(workers);
+    /// ```
+    pub fn supervisor(mut self, supervisor: Supervisor<'a>) -> Supervisor<'a> {
+        self.children.push(Child::Supervisor(supervisor));
+        self
+    }
+
+    /// Returns a handle that cancels the supervisor and all of its children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Executor, RestartStrategy, Supervisor};
+    /// use futures_lite::future;
+    ///
+    /// let ex = Executor::new();
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne)
+    ///     .child(|| future::pending::<Result<(), String>>());
+    ///
+    /// let handle = sup.handle();
+    /// handle.cancel();
+    /// ex.block_on(sup.run(&ex)).unwrap();
+    /// ```
+    pub fn handle(&self) -> SupervisorHandle {
+        SupervisorHandle {
+            cancel: self.cancel.clone(),
+        }
+    }
+
+    /// Runs the children on `ex` until all of them complete, the supervisor gives up or it gets
+    /// cancelled.
+    ///
+    /// Returns the failure that made the supervisor give up. Dropping the returned future cancels
+    /// all children.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{Executor, RestartStrategy, Supervisor};
+    ///
+    /// let ex = Executor::new();
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne)
+    ///     .max_restarts(2, std::time::Duration::from_secs(5))
+    ///     .child(|| async { Err("always fails") });
+    ///
+    /// let failure = ex.block_on(sup.run(&ex)).unwrap_err();
+    /// assert_eq!(failure.error.to_string(), "always fails");
+    /// ```
+    pub async fn run(&self, ex: &Executor<'a>) -> Result<(), TaskFailure> {
+        self.supervise(ex).await
+    }
+
+    /// Returns a boxed future that runs the children, which nested supervisors run as a child.
+    fn supervise<'b>(&'b self, ex: &'b Executor<'a>) -> Running<'b> {
+        Box::pin(async move {
+            let mut running: Vec<Option<Running<'b>>> =
+                self.children.iter().map(|c| Some(c.start(ex))).collect();
+            let mut restart_at: Vec<Option<Instant>> = vec![None; self.children.len()];
+            let mut restarts = VecDeque::new();
+            let mut timer: Option<Sleep> = None;
+            let sleep = ex.state().config.sleep.as_ref();
+
+            future::poll_fn(|cx| loop {
+                if self.cancel.is_cancelled(cx.waker()) {
+                    running.clear();
+                    return Poll::Ready(Ok(()));
+                }
+
+                // Start children whose restart is due.
+                let now = Instant::now();
+                for (i, at) in restart_at.iter_mut().enumerate() {
+                    if at.is_some_and(|at| at <= now) {
+                        *at = None;
+                        running[i] = Some(self.children[i].start(ex));
+                    }
+                }
+
+                // Poll children until one of them fails.
+                let mut failed = None;
+                for (i, child) in running.iter_mut().enumerate() {
+                    if let Some(Poll::Ready(result)) = child.as_mut().map(|c| c.as_mut().poll(cx)) {
+                        *child = None;
+                        if let Err(failure) = result {
+                            failed = Some((i, failure));
+                            break;
+                        }
+                    }
+                }
+
+                if let Some((i, failure)) = failed {
+                    restarts.push_back(now);
+                    while restarts
+                        .front()
+                        .is_some_and(|&t| now.duration_since(t) > self.window)
+                    {
+                        restarts.pop_front();
+                    }
+                    if restarts.len() > self.max_restarts {
+                        event!(task = failure.id, "supervisor gave up");
+                        running.clear();
+                        return Poll::Ready(Err(failure));
+                    }
+
+                    // Cancel the affected children and schedule their restart. The failed child
+                    // is the only affected one that isn't running anymore and hasn't completed.
+                    let affected = match self.strategy {
+                        RestartStrategy::OneForOne => i..i + 1,
+                        RestartStrategy::OneForAll => 0..running.len(),
+                        RestartStrategy::RestForOne => i..running.len(),
+                    };
+                    let at = now + self.delay(restarts.len());
+                    for j in affected {
+                        if j == i || running[j].take().is_some() || restart_at[j].is_some() {
+                            restart_at[j] = Some(at);
+                        }
+                    }
+                    event!(task = failure.id, "restarting supervised children");
+                    continue;
+                }
+
+                // Wait for children or until the next restart is due.
+                let next = restart_at.iter().flatten().min().copied();
+                match next {
+                    None if running.iter().all(Option::is_none) => return Poll::Ready(Ok(())),
+                    None => return Poll::Pending,
+                    Some(next) => {
+                        if timer.as_ref().map(Sleep::deadline) != Some(next) {
+                            timer = Some(Sleep::until(next, sleep));
+                        }
+                        if Pin::new(timer.as_mut().unwrap()).poll(cx).is_pending() {
+                            return Poll::Pending;
+                        }
+                    }
+                }
+            })
+            .await
+        })
+    }
+
+    /// Returns how long to wait before the given restart within the window, counting from 1.
+    fn delay(&self, restart: usize) -> Duration {
+        let (initial, max) = self.backoff;
+        let factor = 1u32 << (restart - 1).min(31);
+        initial.checked_mul(factor).unwrap_or(max).min(max)
+    }
+}
+
+impl fmt::Debug for Supervisor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Supervisor")
+            .field("strategy", &self.strategy)
+            .field("max_restarts", &self.max_restarts)
+            .field("window", &self.window)
+            .field("backoff", &self.backoff)
+            .field("children", &self.children)
+            .field("cancelled", &self.cancel.cancelled.load(Ordering::SeqCst))
+            .finish()
+    }
+}
+
+/// A child of a supervisor.
+enum Child<'a> {
+    /// Creates the future of a task.
+    Task(Box<dyn Fn() -> BoxedChild<'a> + Send + Sync + 'a>),
+
+    /// A nested supervisor.
+    Supervisor(Supervisor<'a>),
+}
+
+impl<'a> Child<'a> {
+    /// Starts the child on `ex`.
+    fn start<'b>(&'b self, ex: &'b Executor<'a>) -> Running<'b> {
+        match self {
+            Child::Task(factory) => Box::pin(ex.spawn_catching(factory())),
+            Child::Supervisor(supervisor) => supervisor.supervise(ex),
+        }
+    }
+}
+
+impl fmt::Debug for Child<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Child::Task(_) => f.write_str("Task"),
+            Child::Supervisor(supervisor) => supervisor.fmt(f),
+        }
+    }
+}
+
+/// Cancels a [`Supervisor`] and all of its children.
+///
+/// Created by [`Supervisor::handle()`].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{RestartStrategy, Supervisor};
+///
+/// let sup = Supervisor::new(RestartStrategy::OneForOne);
+/// let handle = sup.handle();
+///
+/// assert!(!handle.is_cancelled());
+/// handle.cancel();
+/// assert!(handle.is_cancelled());
+/// ```
+#[derive(Debug, Clone)]
+pub struct SupervisorHandle {
+    cancel: Arc<Cancel>,
+}
+
+impl SupervisorHandle {
+    /// Cancels the supervisor.
+    ///
+    /// A running supervisor cancels its children and completes successfully. A supervisor that
+    /// gets run after being cancelled completes right away.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne);
+    /// sup.handle().cancel();
+    /// ```
+    pub fn cancel(&self) {
+        self.cancel.cancelled.store(true, Ordering::SeqCst);
+        if let Some(waker) = self.cancel.waker.lock().take() {
+            waker.wake();
+        }
+    }
+
+    /// Returns `true` if the supervisor was cancelled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use async_executor::{RestartStrategy, Supervisor};
+    ///
+    /// let sup = Supervisor::new(RestartStrategy::OneForOne);
+    /// assert!(!sup.handle().is_cancelled());
+    /// ```
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// Cancellation state shared by a supervisor and its handles.
+#[derive(Debug, Default)]
+struct Cancel {
+    cancelled: AtomicBool,
+
+    /// The waker of the running supervisor.
+    waker: Mutex<Option<Waker>>,
+}
+
+impl Cancel {
+    /// Returns `true` if cancelled, or registers `waker` to be woken on cancellation.
+    fn is_cancelled(&self, waker: &Waker) -> bool {
+        let mut current = self.waker.lock();
+        if self.cancelled.load(Ordering::SeqCst) {
+            return true;
+        }
+        if !current.as_ref().is_some_and(|w| w.will_wake(waker)) {
+            *current = Some(waker.clone());
+        }
+        false
+    }
+}
@@ -1,102 +1,393 @@
-use std::cell::UnsafeCell;
-
 use async_task::Runnable;
 
-use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+mod concurrent;
+mod crossbeam;
+mod custom;
+mod heap;
 
-#[derive(Debug)]
-pub struct GlobalQueue {
-    inner: Injector<Runnable>,
+/// Maximum number of tasks in a local queue.
+const LOCAL_CAPACITY: usize = 256;
+
+/// Maximum number of tasks moved into a local queue by a single steal.
+const STEAL_BATCH: usize = 32;
+
+/// The position of a task in queues that don't run tasks in the order they are scheduled.
+///
+/// Tasks with smaller keys run first.
+pub(crate) type Key = u64;
+
+/// The kind of queues an executor keeps its runnable tasks in.
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::{Builder, QueueKind};
+///
+/// let ex = Builder::new().queue(QueueKind::ConcurrentQueue).build();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueKind {
+    /// Work-stealing queues from `crossbeam-deque`.
+    ///
+    /// This is the default. Every runner has a bounded local queue of tasks, and idle runners
+    /// steal tasks from the global queue and from each other.
+    Crossbeam,
+
+    /// Work-stealing queues built on `concurrent-queue`.
+    ///
+    /// Works like [`QueueKind::Crossbeam`], but steals tasks one at a time.
+    ConcurrentQueue,
+
+    /// A single priority heap shared by all runners.
+    ///
+    /// Runners always pick the task with the highest priority, as given to
+    /// [`Executor::spawn_with_priority()`][crate::Executor::spawn_with_priority]. Tasks of
+    /// equal priority run in the order they were scheduled.
+    Priority,
+
+    /// A single heap shared by all runners, ordered by deadline.
+    ///
+    /// Runners always pick the task with the earliest deadline, as given to
+    /// [`Executor::spawn_with_deadline()`][crate::Executor::spawn_with_deadline]. Tasks without
+    /// a deadline run last, in the order they were scheduled.
+    Deadline,
 }
 
-impl Default for GlobalQueue {
-    fn default() -> Self {
-        Self {
-            inner: Injector::default(),
-        }
-    }
+/// A queue of runnable tasks shared by all runners of an executor.
+///
+/// Together with [`LocalTaskQueue`], this is how an executor stores runnable tasks. The built-in
+/// kinds of queues are selected with [`Builder::queue()`][crate::Builder::queue], and other
+/// implementations can be plugged in with [`Builder::custom_queue()`][crate::Builder::custom_queue].
+///
+/// Every runner has a local queue, which gets tasks woken on the runner's thread. Runners look
+/// for tasks in their local queue first, then in the global queue, and then in the local queues of
+/// other runners.
+///
+/// Every task comes with a key, which is the same on every push. The key is only meaningful to
+/// queues that order tasks: tasks with smaller keys should run first.
+///
+/// # Examples
+///
+/// A single FIFO queue that runners take tasks from one at a time, without stealing from each
+/// other:
+///
+/// ```
+/// use std::collections::VecDeque;
+/// use std::sync::Mutex;
+///
+/// use async_executor::{Builder, LocalTaskQueue, QueueKind, Runnable, TaskQueue};
+///
+/// #[derive(Default)]
+/// struct Fifo(Mutex<VecDeque<Runnable>>);
+///
+/// impl TaskQueue for Fifo {
+///     type Local = Local;
+///
+///     fn push(&self, task: Runnable, _key: u64) {
+///         self.0.lock().unwrap().push_back(task);
+///     }
+///
+///     fn pop(&self) -> Option<Runnable> {
+///         self.0.lock().unwrap().pop_front()
+///     }
+///
+///     fn local(&self) -> Local {
+///         Local(VecDeque::new())
+///     }
+/// }
+///
+/// struct Local(VecDeque<Runnable>);
+///
+/// impl LocalTaskQueue for Local {
+///     type Global = Fifo;
+///     type Handle = ();
+///
+///     fn push(&mut self, task: Runnable, _key: u64) -> Result<(), Runnable> {
+///         self.0.push_back(task);
+///         Ok(())
+///     }
+///
+///     fn pop(&mut self) -> Option<Runnable> {
+///         self.0.pop_front()
+///     }
+///
+///     fn is_empty(&self) -> bool {
+///         self.0.is_empty()
+///     }
+///
+///     fn append(&mut self, other: &mut Local, _global: &Fifo) -> bool {
+///         self.0.append(&mut other.0);
+///         false
+///     }
+///
+///     fn spill(&mut self, global: &Fifo) {
+///         let half = self.0.len() / 2;
+///         global.0.lock().unwrap().extend(self.0.drain(..half));
+///     }
+///
+///     fn flush(&mut self, global: &Fifo) {
+///         global.0.lock().unwrap().extend(self.0.drain(..));
+///     }
+///
+///     fn steal_global(&mut self, global: &Fifo) {
+///         self.0.extend(global.pop());
+///     }
+///
+///     fn steal_local(&mut self, _other: &()) {}
+///
+///     fn handle(&self) {}
+/// }
+///
+/// let ex = Builder::new()
+///     .custom_queue::<Fifo>(QueueKind::Crossbeam)
+///     .build();
+///
+/// let task = ex.spawn(async { 1 + 2 });
+/// assert_eq!(ex.block_on(task), 3);
+/// ```
+pub trait TaskQueue: Send + Sync + 'static {
+    /// The matching kind of local queue.
+    type Local: LocalTaskQueue<Global = Self>;
+
+    /// Pushes a task.
+    fn push(&self, task: Runnable, key: u64);
+
+    /// Pops the next task to run.
+    fn pop(&self) -> Option<Runnable>;
+
+    /// Creates an empty local queue for a runner.
+    fn local(&self) -> Self::Local;
 }
 
-impl GlobalQueue {
-    pub fn push(&self, task: Runnable) {
-        self.inner.push(task)
-    }
+/// A queue of runnable tasks owned by a runner, which other runners can steal from.
+///
+/// See [`TaskQueue`] for how queues are used and an example.
+pub trait LocalTaskQueue: Send + 'static {
+    /// The matching kind of global queue.
+    type Global: TaskQueue<Local = Self>;
 
-    pub fn pop(&self) -> Option<Runnable> {
-        loop {
-            match self.inner.steal() {
-                Steal::Retry => continue,
-                Steal::Empty => return None,
-                Steal::Success(v) => return Some(v),
-            }
-        }
-    }
+    /// A handle other runners steal tasks through.
+    type Handle: Clone + Send + Sync + 'static;
+
+    /// Pushes a task, or returns it back if the queue is full.
+    ///
+    /// A task that is returned gets pushed into the global queue, after half of this queue has
+    /// been [spilled][LocalTaskQueue::spill] there.
+    fn push(&mut self, task: Runnable, key: u64) -> Result<(), Runnable>;
+
+    /// Pops the next task to run.
+    fn pop(&mut self) -> Option<Runnable>;
+
+    /// Returns `true` if the queue is empty.
+    fn is_empty(&self) -> bool;
+
+    /// Moves all tasks from `other` into this queue.
+    ///
+    /// If this queue fills up, half of it is spilled into the global queue and `true` is
+    /// returned.
+    fn append(&mut self, other: &mut Self, global: &Self::Global) -> bool;
+
+    /// Moves the older half of the tasks into the global queue.
+    ///
+    /// Called when the queue is full, so that other runners can pick up the spilled tasks.
+    fn spill(&mut self, global: &Self::Global);
+
+    /// Moves all tasks into the global queue.
+    fn flush(&mut self, global: &Self::Global);
+
+    /// Moves some tasks from the global queue into this queue.
+    ///
+    /// Runners only run tasks from their local queue, so this must move at least one task if the
+    /// global queue isn't empty.
+    fn steal_global(&mut self, global: &Self::Global);
+
+    /// Moves some tasks from another runner's queue into this queue.
+    fn steal_local(&mut self, other: &Self::Handle);
+
+    /// Returns a handle for other runners to steal from this queue.
+    fn handle(&self) -> Self::Handle;
 }
 
-#[derive(Debug, Clone)]
-pub struct LocalQueueHandle {
-    inner: Stealer<Runnable>,
+/// The global queue of an executor, of the configured kind.
+///
+/// Every executor has only one, so the size of the largest variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub(crate) enum GlobalQueue {
+    Crossbeam(crossbeam::Global),
+    Concurrent(concurrent::Global),
+    Heap(heap::Global),
+    Custom(custom::Global),
 }
 
+/// A runner's local queue, of the same kind as the global queue.
 #[derive(Debug)]
-pub struct LocalQueue {
-    inner: Worker<Runnable>,
-    next_task: Option<Runnable>,
+pub(crate) enum LocalQueue {
+    Crossbeam(crossbeam::Local),
+    Concurrent(concurrent::Local),
+    Heap(heap::Local),
+    Custom(custom::Local),
 }
 
-impl Default for LocalQueue {
-    fn default() -> Self {
-        Self {
-            inner: Worker::new_fifo(),
-            next_task: Default::default(),
+/// A handle for stealing from a [`LocalQueue`].
+#[derive(Debug, Clone)]
+pub(crate) enum LocalQueueHandle {
+    Crossbeam(crossbeam::Handle),
+    Concurrent(concurrent::Handle),
+    Heap(heap::Handle),
+    Custom(custom::Handle),
+}
+
+impl GlobalQueue {
+    /// Creates an empty global queue of the given kind.
+    pub(crate) fn new(kind: QueueKind) -> GlobalQueue {
+        match kind {
+            QueueKind::Crossbeam => GlobalQueue::Crossbeam(Default::default()),
+            QueueKind::ConcurrentQueue => GlobalQueue::Concurrent(Default::default()),
+            QueueKind::Priority | QueueKind::Deadline => GlobalQueue::Heap(Default::default()),
         }
     }
+
+    /// Creates an empty global queue of a custom type.
+    pub(crate) fn custom<Q: TaskQueue + Default>() -> GlobalQueue {
+        GlobalQueue::Custom(custom::Global::new(Q::default()))
+    }
 }
 
-impl LocalQueue {
+impl TaskQueue for GlobalQueue {
+    type Local = LocalQueue;
+
     #[inline]
-    pub fn push(&mut self, is_yield: bool, task: Runnable) -> Result<(), Runnable> {
-        // if this is the same task as last time, we don't push to next_task
-        // if is_yield {
-        self.inner.push(task);
-        // } else {
-        //     let next_task = self.next_task();
-        //     if let Some(task) = next_task.replace(task) {
-        //         self.inner.push(task);
-        //     }
-        // }
-        Ok(())
+    fn push(&self, task: Runnable, key: Key) {
+        match self {
+            GlobalQueue::Crossbeam(q) => q.push(task, key),
+            GlobalQueue::Concurrent(q) => q.push(task, key),
+            GlobalQueue::Heap(q) => q.push(task, key),
+            GlobalQueue::Custom(q) => q.push(task, key),
+        }
     }
 
     #[inline]
-    pub fn pop(&mut self) -> Option<Runnable> {
-        // let next_task = self.next_task();
-        // if let Some(next_task) = next_task.take() {
-        //     Some(next_task)
-        // } else {
-        self.inner.pop()
-        // }
+    fn pop(&self) -> Option<Runnable> {
+        match self {
+            GlobalQueue::Crossbeam(q) => q.pop(),
+            GlobalQueue::Concurrent(q) => q.pop(),
+            GlobalQueue::Heap(q) => q.pop(),
+            GlobalQueue::Custom(q) => q.pop(),
+        }
     }
 
+    fn local(&self) -> LocalQueue {
+        match self {
+            GlobalQueue::Crossbeam(q) => LocalQueue::Crossbeam(q.local()),
+            GlobalQueue::Concurrent(q) => LocalQueue::Concurrent(q.local()),
+            GlobalQueue::Heap(q) => LocalQueue::Heap(q.local()),
+            GlobalQueue::Custom(q) => LocalQueue::Custom(q.local()),
+        }
+    }
+}
+
+/// Message for queues of different kinds meeting, which can't happen within one executor.
+const MISMATCH: &str = "queues of different kinds";
+
+impl LocalTaskQueue for LocalQueue {
+    type Global = GlobalQueue;
+    type Handle = LocalQueueHandle;
+
     #[inline]
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    fn push(&mut self, task: Runnable, key: Key) -> Result<(), Runnable> {
+        match self {
+            LocalQueue::Crossbeam(q) => q.push(task, key),
+            LocalQueue::Concurrent(q) => q.push(task, key),
+            LocalQueue::Heap(q) => q.push(task, key),
+            LocalQueue::Custom(q) => q.push(task, key),
+        }
     }
 
     #[inline]
-    pub fn steal_global(&self, other: &GlobalQueue) {
-        std::iter::repeat_with(|| other.inner.steal_batch(&self.inner)).find(|v| !v.is_retry());
+    fn pop(&mut self) -> Option<Runnable> {
+        match self {
+            LocalQueue::Crossbeam(q) => q.pop(),
+            LocalQueue::Concurrent(q) => q.pop(),
+            LocalQueue::Heap(q) => q.pop(),
+            LocalQueue::Custom(q) => q.pop(),
+        }
     }
 
     #[inline]
-    pub fn steal_local(&self, other: &LocalQueueHandle) {
-        let _ = other.inner.steal_batch(&self.inner);
+    fn is_empty(&self) -> bool {
+        match self {
+            LocalQueue::Crossbeam(q) => q.is_empty(),
+            LocalQueue::Concurrent(q) => q.is_empty(),
+            LocalQueue::Heap(q) => q.is_empty(),
+            LocalQueue::Custom(q) => q.is_empty(),
+        }
+    }
+
+    fn append(&mut self, other: &mut LocalQueue, global: &GlobalQueue) -> bool {
+        match (self, other, global) {
+            (LocalQueue::Crossbeam(q), LocalQueue::Crossbeam(o), GlobalQueue::Crossbeam(g)) => {
+                q.append(o, g)
+            }
+            (LocalQueue::Concurrent(q), LocalQueue::Concurrent(o), GlobalQueue::Concurrent(g)) => {
+                q.append(o, g)
+            }
+            (LocalQueue::Heap(q), LocalQueue::Heap(o), GlobalQueue::Heap(g)) => q.append(o, g),
+            (LocalQueue::Custom(q), LocalQueue::Custom(o), GlobalQueue::Custom(g)) => {
+                q.append(o, g)
+            }
+            _ => unreachable!("{}", MISMATCH),
+        }
+    }
+
+    fn spill(&mut self, global: &GlobalQueue) {
+        match (self, global) {
+            (LocalQueue::Crossbeam(q), GlobalQueue::Crossbeam(g)) => q.spill(g),
+            (LocalQueue::Concurrent(q), GlobalQueue::Concurrent(g)) => q.spill(g),
+            (LocalQueue::Heap(q), GlobalQueue::Heap(g)) => q.spill(g),
+            (LocalQueue::Custom(q), GlobalQueue::Custom(g)) => q.spill(g),
+            _ => unreachable!("{}", MISMATCH),
+        }
+    }
+
+    fn flush(&mut self, global: &GlobalQueue) {
+        match (self, global) {
+            (LocalQueue::Crossbeam(q), GlobalQueue::Crossbeam(g)) => q.flush(g),
+            (LocalQueue::Concurrent(q), GlobalQueue::Concurrent(g)) => q.flush(g),
+            (LocalQueue::Heap(q), GlobalQueue::Heap(g)) => q.flush(g),
+            (LocalQueue::Custom(q), GlobalQueue::Custom(g)) => q.flush(g),
+            _ => unreachable!("{}", MISMATCH),
+        }
+    }
+
+    #[inline]
+    fn steal_global(&mut self, global: &GlobalQueue) {
+        match (self, global) {
+            (LocalQueue::Crossbeam(q), GlobalQueue::Crossbeam(g)) => q.steal_global(g),
+            (LocalQueue::Concurrent(q), GlobalQueue::Concurrent(g)) => q.steal_global(g),
+            (LocalQueue::Heap(q), GlobalQueue::Heap(g)) => q.steal_global(g),
+            (LocalQueue::Custom(q), GlobalQueue::Custom(g)) => q.steal_global(g),
+            _ => unreachable!("{}", MISMATCH),
+        }
     }
 
     #[inline]
-    pub fn handle(&self) -> LocalQueueHandle {
-        LocalQueueHandle {
-            inner: self.inner.stealer(),
+    fn steal_local(&mut self, other: &LocalQueueHandle) {
+        match (self, other) {
+            (LocalQueue::Crossbeam(q), LocalQueueHandle::Crossbeam(o)) => q.steal_local(o),
+            (LocalQueue::Concurrent(q), LocalQueueHandle::Concurrent(o)) => q.steal_local(o),
+            (LocalQueue::Heap(q), LocalQueueHandle::Heap(o)) => q.steal_local(o),
+            (LocalQueue::Custom(q), LocalQueueHandle::Custom(o)) => q.steal_local(o),
+            _ => unreachable!("{}", MISMATCH),
+        }
+    }
+
+    fn handle(&self) -> LocalQueueHandle {
+        match self {
+            LocalQueue::Crossbeam(q) => LocalQueueHandle::Crossbeam(q.handle()),
+            LocalQueue::Concurrent(q) => LocalQueueHandle::Concurrent(q.handle()),
+            LocalQueue::Heap(q) => LocalQueueHandle::Heap(q.handle()),
+            LocalQueue::Custom(q) => LocalQueueHandle::Custom(q.handle()),
         }
     }
 }
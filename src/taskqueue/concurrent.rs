@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use async_task::Runnable;
+use concurrent_queue::{ConcurrentQueue, PushError};
+
+use super::{Key, LocalTaskQueue, TaskQueue, LOCAL_CAPACITY, STEAL_BATCH};
+
+#[derive(Debug)]
+pub(crate) struct Global {
+    inner: ConcurrentQueue<Runnable>,
+}
+
+impl Default for Global {
+    fn default() -> Global {
+        Global {
+            inner: ConcurrentQueue::unbounded(),
+        }
+    }
+}
+
+impl TaskQueue for Global {
+    type Local = Local;
+
+    #[inline]
+    fn push(&self, task: Runnable, _key: Key) {
+        // The global queue is unbounded and never closed.
+        self.inner.push(task).ok();
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<Runnable> {
+        self.inner.pop().ok()
+    }
+
+    fn local(&self) -> Local {
+        Local {
+            inner: Arc::new(ConcurrentQueue::bounded(LOCAL_CAPACITY)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Handle {
+    inner: Arc<ConcurrentQueue<Runnable>>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Local {
+    inner: Arc<ConcurrentQueue<Runnable>>,
+}
+
+impl Local {
+    /// Moves up to `limit` tasks from `source` into this queue.
+    fn steal_from(&self, source: &ConcurrentQueue<Runnable>, limit: usize) {
+        // Only this runner pushes into its queue, so the room can't shrink meanwhile.
+        let limit = limit.min(LOCAL_CAPACITY - self.inner.len());
+        for _ in 0..limit {
+            match source.pop() {
+                Ok(task) => {
+                    if self.inner.push(task).is_err() {
+                        unreachable!("local queue overflow");
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    }
+}
+
+impl LocalTaskQueue for Local {
+    type Global = Global;
+    type Handle = Handle;
+
+    #[inline]
+    fn push(&mut self, task: Runnable, _key: Key) -> Result<(), Runnable> {
+        self.inner.push(task).map_err(|err| match err {
+            PushError::Full(task) | PushError::Closed(task) => task,
+        })
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<Runnable> {
+        self.inner.pop().ok()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool {
+        let mut spilled = false;
+        while let Ok(mut task) = other.inner.pop() {
+            while let Err(err) = self.inner.push(task) {
+                task = err.into_inner();
+                self.spill(global);
+                spilled = true;
+            }
+        }
+        spilled
+    }
+
+    fn spill(&mut self, global: &Global) {
+        for _ in 0..self.inner.len() / 2 {
+            match self.inner.pop() {
+                Ok(task) => global.push(task, 0),
+                Err(_) => break,
+            }
+        }
+    }
+
+    fn flush(&mut self, global: &Global) {
+        while let Ok(task) = self.inner.pop() {
+            global.push(task, 0);
+        }
+    }
+
+    #[inline]
+    fn steal_global(&mut self, global: &Global) {
+        self.steal_from(&global.inner, STEAL_BATCH);
+    }
+
+    #[inline]
+    fn steal_local(&mut self, other: &Handle) {
+        // Take half of the other runner's tasks, like the crossbeam queues do.
+        let half = other.inner.len().div_ceil(2);
+        self.steal_from(&other.inner, half.min(STEAL_BATCH));
+    }
+
+    #[inline]
+    fn handle(&self) -> Handle {
+        Handle {
+            inner: self.inner.clone(),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use async_task::Runnable;
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+
+use super::{Key, LocalTaskQueue, TaskQueue, LOCAL_CAPACITY, STEAL_BATCH};
+
+#[derive(Debug, Default)]
+pub(crate) struct Global {
+    inner: Injector<Runnable>,
+}
+
+impl TaskQueue for Global {
+    type Local = Local;
+
+    #[inline]
+    fn push(&self, task: Runnable, _key: Key) {
+        self.inner.push(task)
+    }
+
+    fn pop(&self) -> Option<Runnable> {
+        loop {
+            match self.inner.steal() {
+                Steal::Retry => continue,
+                Steal::Empty => return None,
+                Steal::Success(v) => return Some(v),
+            }
+        }
+    }
+
+    fn local(&self) -> Local {
+        Local {
+            inner: Worker::new_fifo(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Handle {
+    inner: Stealer<Runnable>,
+}
+
+#[derive(Debug)]
+pub(crate) struct Local {
+    inner: Worker<Runnable>,
+}
+
+impl Local {
+    /// Returns how many tasks a steal may move into the queue without exceeding its capacity.
+    #[inline]
+    fn room(&self) -> usize {
+        LOCAL_CAPACITY
+            .saturating_sub(self.inner.len())
+            .min(STEAL_BATCH)
+    }
+}
+
+impl LocalTaskQueue for Local {
+    type Global = Global;
+    type Handle = Handle;
+
+    #[inline]
+    fn push(&mut self, task: Runnable, _key: Key) -> Result<(), Runnable> {
+        if self.inner.len() >= LOCAL_CAPACITY {
+            return Err(task);
+        }
+        self.inner.push(task);
+        Ok(())
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<Runnable> {
+        self.inner.pop()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool {
+        let mut spilled = false;
+        while let Some(task) = other.inner.pop() {
+            if self.inner.len() >= LOCAL_CAPACITY {
+                self.spill(global);
+                spilled = true;
+            }
+            self.inner.push(task);
+        }
+        spilled
+    }
+
+    fn spill(&mut self, global: &Global) {
+        for _ in 0..self.inner.len() / 2 {
+            match self.inner.pop() {
+                Some(task) => global.inner.push(task),
+                None => break,
+            }
+        }
+    }
+
+    fn flush(&mut self, global: &Global) {
+        while let Some(task) = self.inner.pop() {
+            global.inner.push(task);
+        }
+    }
+
+    #[inline]
+    fn steal_global(&mut self, global: &Global) {
+        let limit = self.room();
+        if limit > 0 {
+            std::iter::repeat_with(|| global.inner.steal_batch_with_limit(&self.inner, limit))
+                .find(|v| !v.is_retry());
+        }
+    }
+
+    #[inline]
+    fn steal_local(&mut self, other: &Handle) {
+        let limit = self.room();
+        if limit > 0 {
+            let _ = other.inner.steal_batch_with_limit(&self.inner, limit);
+        }
+    }
+
+    #[inline]
+    fn handle(&self) -> Handle {
+        Handle {
+            inner: self.inner.stealer(),
+        }
+    }
+}
@@ -0,0 +1,210 @@
+use std::any::Any;
+use std::fmt;
+use std::sync::Arc;
+
+use async_task::Runnable;
+
+use super::{Key, LocalTaskQueue, TaskQueue, MISMATCH};
+
+/// A global queue of a type supplied by the user.
+pub(crate) struct Global {
+    inner: Box<dyn DynGlobal>,
+}
+
+impl Global {
+    /// Wraps a global queue.
+    pub(crate) fn new<Q: TaskQueue>(queue: Q) -> Global {
+        Global {
+            inner: Box::new(queue),
+        }
+    }
+
+    /// Returns the wrapped queue, which must be a `Q`.
+    fn downcast<Q: 'static>(&self) -> &Q {
+        self.inner.as_any().downcast_ref().expect(MISMATCH)
+    }
+}
+
+impl fmt::Debug for Global {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Global").finish_non_exhaustive()
+    }
+}
+
+impl TaskQueue for Global {
+    type Local = Local;
+
+    #[inline]
+    fn push(&self, task: Runnable, key: Key) {
+        self.inner.push(task, key)
+    }
+
+    #[inline]
+    fn pop(&self) -> Option<Runnable> {
+        self.inner.pop()
+    }
+
+    fn local(&self) -> Local {
+        self.inner.local()
+    }
+}
+
+/// A handle for stealing from a [`Local`].
+#[derive(Clone)]
+pub(crate) struct Handle {
+    inner: Arc<dyn Any + Send + Sync>,
+}
+
+impl fmt::Debug for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Handle").finish_non_exhaustive()
+    }
+}
+
+/// A local queue of a type supplied by the user.
+pub(crate) struct Local {
+    inner: Box<dyn DynLocal>,
+}
+
+impl fmt::Debug for Local {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Local").finish_non_exhaustive()
+    }
+}
+
+impl LocalTaskQueue for Local {
+    type Global = Global;
+    type Handle = Handle;
+
+    #[inline]
+    fn push(&mut self, task: Runnable, key: Key) -> Result<(), Runnable> {
+        self.inner.push(task, key)
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<Runnable> {
+        self.inner.pop()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool {
+        self.inner.append(other, global)
+    }
+
+    fn spill(&mut self, global: &Global) {
+        self.inner.spill(global)
+    }
+
+    fn flush(&mut self, global: &Global) {
+        self.inner.flush(global)
+    }
+
+    #[inline]
+    fn steal_global(&mut self, global: &Global) {
+        self.inner.steal_global(global)
+    }
+
+    #[inline]
+    fn steal_local(&mut self, other: &Handle) {
+        self.inner.steal_local(other)
+    }
+
+    fn handle(&self) -> Handle {
+        self.inner.handle()
+    }
+}
+
+/// An object-safe version of [`TaskQueue`].
+trait DynGlobal: Send + Sync {
+    fn push(&self, task: Runnable, key: Key);
+    fn pop(&self) -> Option<Runnable>;
+    fn local(&self) -> Local;
+    fn as_any(&self) -> &dyn Any;
+}
+
+impl<Q: TaskQueue> DynGlobal for Q {
+    fn push(&self, task: Runnable, key: Key) {
+        TaskQueue::push(self, task, key)
+    }
+
+    fn pop(&self) -> Option<Runnable> {
+        TaskQueue::pop(self)
+    }
+
+    fn local(&self) -> Local {
+        Local {
+            inner: Box::new(TaskQueue::local(self)),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// An object-safe version of [`LocalTaskQueue`].
+///
+/// The other queues passed in were all created by the same executor, so they are of the
+/// matching types.
+trait DynLocal: Send {
+    fn push(&mut self, task: Runnable, key: Key) -> Result<(), Runnable>;
+    fn pop(&mut self) -> Option<Runnable>;
+    fn is_empty(&self) -> bool;
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool;
+    fn spill(&mut self, global: &Global);
+    fn flush(&mut self, global: &Global);
+    fn steal_global(&mut self, global: &Global);
+    fn steal_local(&mut self, other: &Handle);
+    fn handle(&self) -> Handle;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<L: LocalTaskQueue> DynLocal for L {
+    fn push(&mut self, task: Runnable, key: Key) -> Result<(), Runnable> {
+        LocalTaskQueue::push(self, task, key)
+    }
+
+    fn pop(&mut self) -> Option<Runnable> {
+        LocalTaskQueue::pop(self)
+    }
+
+    fn is_empty(&self) -> bool {
+        LocalTaskQueue::is_empty(self)
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool {
+        let other = other.inner.as_any_mut().downcast_mut().expect(MISMATCH);
+        LocalTaskQueue::append(self, other, global.downcast())
+    }
+
+    fn spill(&mut self, global: &Global) {
+        LocalTaskQueue::spill(self, global.downcast())
+    }
+
+    fn flush(&mut self, global: &Global) {
+        LocalTaskQueue::flush(self, global.downcast())
+    }
+
+    fn steal_global(&mut self, global: &Global) {
+        LocalTaskQueue::steal_global(self, global.downcast())
+    }
+
+    fn steal_local(&mut self, other: &Handle) {
+        let other = other.inner.downcast_ref().expect(MISMATCH);
+        LocalTaskQueue::steal_local(self, other)
+    }
+
+    fn handle(&self) -> Handle {
+        Handle {
+            inner: Arc::new(LocalTaskQueue::handle(self)),
+        }
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
@@ -0,0 +1,138 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+use async_task::Runnable;
+use parking_lot::Mutex;
+
+use super::{Key, LocalTaskQueue, TaskQueue};
+
+/// A task in the heap.
+#[derive(Debug)]
+struct Entry {
+    key: Key,
+
+    /// Breaks ties between equal keys in the order tasks were pushed.
+    seq: u64,
+
+    task: Runnable,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        (self.key, self.seq) == (other.key, other.seq)
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        (self.key, self.seq).cmp(&(other.key, other.seq))
+    }
+}
+
+#[derive(Debug, Default)]
+struct Heap {
+    entries: BinaryHeap<Reverse<Entry>>,
+    seq: u64,
+}
+
+/// A single heap of tasks ordered by key.
+///
+/// Local queues hold at most the one task a runner is about to run, so that runners always
+/// pick the task with the smallest key across the whole executor.
+#[derive(Debug, Default)]
+pub(crate) struct Global {
+    heap: Mutex<Heap>,
+}
+
+impl Global {
+    /// Pops the next task to run along with its key.
+    fn pop_entry(&self) -> Option<(Runnable, Key)> {
+        let Reverse(entry) = self.heap.lock().entries.pop()?;
+        Some((entry.task, entry.key))
+    }
+}
+
+impl TaskQueue for Global {
+    type Local = Local;
+
+    fn push(&self, task: Runnable, key: Key) {
+        let mut heap = self.heap.lock();
+        let seq = heap.seq;
+        heap.seq += 1;
+        heap.entries.push(Reverse(Entry { key, seq, task }));
+    }
+
+    fn pop(&self) -> Option<Runnable> {
+        self.pop_entry().map(|(task, _)| task)
+    }
+
+    fn local(&self) -> Local {
+        Local { next: None }
+    }
+}
+
+/// Nothing to steal, since local queues only hold tasks that are about to run.
+#[derive(Debug, Clone)]
+pub(crate) struct Handle;
+
+#[derive(Debug)]
+pub(crate) struct Local {
+    next: Option<(Runnable, Key)>,
+}
+
+impl LocalTaskQueue for Local {
+    type Global = Global;
+    type Handle = Handle;
+
+    #[inline]
+    fn push(&mut self, task: Runnable, _key: Key) -> Result<(), Runnable> {
+        // Tasks must go through the heap to run in order.
+        Err(task)
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<Runnable> {
+        self.next.take().map(|(task, _)| task)
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.next.is_none()
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Global) -> bool {
+        other.flush(global);
+        false
+    }
+
+    fn spill(&mut self, _global: &Global) {}
+
+    fn flush(&mut self, global: &Global) {
+        if let Some((task, key)) = self.next.take() {
+            global.push(task, key);
+        }
+    }
+
+    #[inline]
+    fn steal_global(&mut self, global: &Global) {
+        if self.next.is_none() {
+            self.next = global.pop_entry();
+        }
+    }
+
+    #[inline]
+    fn steal_local(&mut self, _other: &Handle) {}
+
+    #[inline]
+    fn handle(&self) -> Handle {
+        Handle
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, Thread};
+use std::time::Instant;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::atomic::AtomicU64;
+
+/// The thread that wakes sleeping futures, started on first use.
+static TIMERS: Lazy<TimerThread> = Lazy::new(|| {
+    let handle = thread::Builder::new()
+        .name("async-executor-timer".to_string())
+        .spawn(|| TIMERS.run())
+        .expect("cannot spawn the timer thread");
+
+    TimerThread {
+        timers: Mutex::new(BTreeMap::new()),
+        next_id: AtomicU64::new(0),
+        thread: handle.thread().clone(),
+    }
+});
+
+/// Wakers of sleeping futures, ordered by deadline.
+#[derive(Debug)]
+struct TimerThread {
+    /// Wakers keyed by deadline and a unique ID.
+    timers: Mutex<BTreeMap<(Instant, u64), Waker>>,
+
+    /// The ID of the next timer.
+    next_id: AtomicU64,
+
+    /// The timer thread.
+    thread: Thread,
+}
+
+impl TimerThread {
+    /// Wakes futures whose deadline has passed, forever.
+    fn run(&self) -> ! {
+        loop {
+            let (ready, next) = {
+                let mut timers = self.timers.lock();
+                let later = timers.split_off(&(Instant::now(), u64::MAX));
+                let ready = mem::replace(&mut *timers, later);
+                (ready, timers.keys().next().map(|(deadline, _)| *deadline))
+            };
+            for waker in ready.into_values() {
+                waker.wake();
+            }
+
+            match next {
+                Some(deadline) => {
+                    thread::park_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => thread::park(),
+            }
+        }
+    }
+
+    /// Registers a waker to wake at `deadline`.
+    fn insert(&self, deadline: Instant, waker: &Waker) -> (Instant, u64) {
+        let key = (deadline, self.next_id.fetch_add(1, Ordering::Relaxed));
+        let mut timers = self.timers.lock();
+        timers.insert(key, waker.clone());
+
+        // The thread sleeps until the earliest deadline, which has just changed.
+        if timers.keys().next() == Some(&key) {
+            self.thread.unpark();
+        }
+        key
+    }
+
+    /// Replaces the waker of a timer unless it was woken already.
+    fn update(&self, key: (Instant, u64), waker: &Waker) {
+        if let Some(w) = self.timers.lock().get_mut(&key) {
+            if !w.will_wake(waker) {
+                *w = waker.clone();
+            }
+        }
+    }
+
+    /// Unregisters a timer.
+    fn remove(&self, key: (Instant, u64)) {
+        self.timers.lock().remove(&key);
+    }
+}
+
+/// A future that completes at a deadline, created by a function set on the builder.
+pub(crate) type BoxedSleep = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// A function set on the builder that sleeps until a deadline.
+#[derive(Clone)]
+pub(crate) struct SleepHook(pub(crate) Arc<dyn Fn(Instant) -> BoxedSleep + Send + Sync>);
+
+impl fmt::Debug for SleepHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SleepHook").finish_non_exhaustive()
+    }
+}
+
+/// A future that completes at a deadline.
+pub(crate) struct Sleep {
+    deadline: Instant,
+
+    /// The future created by the sleep hook, if one was set.
+    hook: Option<BoxedSleep>,
+
+    /// The key of the timer registered with the timer thread.
+    key: Option<(Instant, u64)>,
+}
+
+impl Sleep {
+    /// Creates a future that completes at `deadline`, using `hook` if there is one and the timer
+    /// thread otherwise.
+    pub(crate) fn until(deadline: Instant, hook: Option<&SleepHook>) -> Sleep {
+        Sleep {
+            deadline,
+            hook: hook.map(|hook| (hook.0)(deadline)),
+            key: None,
+        }
+    }
+
+    /// Returns the deadline.
+    pub(crate) fn deadline(&self) -> Instant {
+        self.deadline
+    }
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(hook) = &mut self.hook {
+            return hook.as_mut().poll(cx);
+        }
+
+        if Instant::now() >= self.deadline {
+            if let Some(key) = self.key.take() {
+                TIMERS.remove(key);
+            }
+            return Poll::Ready(());
+        }
+
+        match self.key {
+            None => self.key = Some(TIMERS.insert(self.deadline, cx.waker())),
+            Some(key) => TIMERS.update(key, cx.waker()),
+        }
+        Poll::Pending
+    }
+}
+
+impl Drop for Sleep {
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            TIMERS.remove(key);
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use std::convert::TryFrom;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::atomic::AtomicU64;
+
+/// Time spent polling a task.
+///
+/// Collected by executors configured with [`Builder::time_tasks()`][crate::Builder::time_tasks].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::Builder;
+///
+/// let ex = Builder::new().time_tasks(true).build();
+///
+/// let task = ex.spawn(async {});
+/// for times in ex.task_times() {
+///     println!("task {} was polled {} times", times.id, times.polls);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskTimes {
+    /// The ID of the task, unique within its executor.
+    pub id: u64,
+
+    /// Number of times the task was polled.
+    pub polls: u64,
+
+    /// Wall-clock time spent polling the task.
+    pub wall_time: Duration,
+
+    /// CPU time the polling threads spent polling the task.
+    ///
+    /// Only measured on Linux.
+    pub cpu_time: Option<Duration>,
+}
+
+/// Running totals for one task.
+#[derive(Debug)]
+pub(crate) struct TaskTimer {
+    id: u64,
+    polls: AtomicU64,
+
+    /// Wall-clock time in nanoseconds.
+    wall_time: AtomicU64,
+
+    /// CPU time in nanoseconds.
+    cpu_time: AtomicU64,
+}
+
+impl TaskTimer {
+    /// Runs one poll of the task and adds the time it took.
+    pub(crate) fn measure<T>(&self, poll: impl FnOnce() -> T) -> T {
+        let cpu_start = thread_cpu_time();
+        let start = Instant::now();
+        let output = poll();
+        let wall = start.elapsed();
+        let cpu = thread_cpu_time()
+            .zip(cpu_start)
+            .map(|(end, start)| end.saturating_sub(start));
+
+        self.polls.fetch_add(1, Ordering::Relaxed);
+        self.wall_time.fetch_add(nanos(wall), Ordering::Relaxed);
+        if let Some(cpu) = cpu {
+            self.cpu_time.fetch_add(nanos(cpu), Ordering::Relaxed);
+        }
+        output
+    }
+
+    /// Returns the totals so far.
+    pub(crate) fn times(&self) -> TaskTimes {
+        TaskTimes {
+            id: self.id,
+            polls: self.polls.load(Ordering::Relaxed),
+            wall_time: Duration::from_nanos(self.wall_time.load(Ordering::Relaxed)),
+            cpu_time: if cfg!(target_os = "linux") {
+                Some(Duration::from_nanos(self.cpu_time.load(Ordering::Relaxed)))
+            } else {
+                None
+            },
+        }
+    }
+}
+
+/// Timers of all unfinished tasks of an executor.
+#[derive(Debug, Default)]
+pub(crate) struct Timers {
+    live: Mutex<Slab<Arc<TaskTimer>>>,
+}
+
+impl Timers {
+    /// Creates a timer for a new task with the given ID.
+    ///
+    /// The timer is removed from the list of live timers when the returned guard is dropped.
+    pub(crate) fn register(self: &Arc<Self>, id: u64) -> TimerGuard {
+        let timer = Arc::new(TaskTimer {
+            id,
+            polls: AtomicU64::new(0),
+            wall_time: AtomicU64::new(0),
+            cpu_time: AtomicU64::new(0),
+        });
+        let key = self.live.lock().insert(timer.clone());
+
+        TimerGuard {
+            timers: self.clone(),
+            key,
+            timer,
+        }
+    }
+
+    /// Returns the totals of all unfinished tasks.
+    pub(crate) fn snapshot(&self) -> Vec<TaskTimes> {
+        self.live.lock().iter().map(|(_, t)| t.times()).collect()
+    }
+}
+
+/// A task's timer, registered in the list of live timers.
+#[derive(Debug)]
+pub(crate) struct TimerGuard {
+    timers: Arc<Timers>,
+    key: usize,
+    timer: Arc<TaskTimer>,
+}
+
+impl std::ops::Deref for TimerGuard {
+    type Target = TaskTimer;
+
+    fn deref(&self) -> &TaskTimer {
+        &self.timer
+    }
+}
+
+impl Drop for TimerGuard {
+    fn drop(&mut self) {
+        self.timers.live.lock().try_remove(self.key);
+    }
+}
+
+/// Converts a duration to nanoseconds, saturating on overflow.
+fn nanos(duration: Duration) -> u64 {
+    u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX)
+}
+
+/// Returns the CPU time consumed by the current thread.
+#[cfg(target_os = "linux")]
+fn thread_cpu_time() -> Option<Duration> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `ts` is a valid pointer to a `timespec`.
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) } != 0 {
+        return None;
+    }
+    Some(Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32))
+}
+
+/// Returns the CPU time consumed by the current thread.
+#[cfg(not(target_os = "linux"))]
+fn thread_cpu_time() -> Option<Duration> {
+    None
+}
@@ -0,0 +1,78 @@
+//! Instrumentation with the `tracing` crate, enabled by the `tracing` feature.
+//!
+//! Every task gets a span that is a child of the span current at the time of spawning, and every
+//! poll of the task is a child span of that. Runners emit events when they park, unpark and
+//! steal tasks, and executors emit an event when they shut down.
+//!
+//! With the `console` feature, task spans and waker events follow the conventions of
+//! `console-subscriber` instead.
+
+#[cfg(feature = "tracing")]
+use std::future::Future;
+#[cfg(feature = "tracing")]
+use std::panic::Location;
+#[cfg(feature = "console")]
+use std::task::Context;
+
+#[cfg(feature = "tracing")]
+use futures_lite::future;
+#[cfg(feature = "tracing")]
+use tracing::Span;
+
+/// Emits a trace-level event if the `tracing` feature is enabled.
+macro_rules! event {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(target: "async_executor", $($arg)*);
+    };
+}
+
+/// Creates the span of a new task.
+#[cfg(all(feature = "tracing", not(feature = "console")))]
+pub(crate) fn task_span(id: u64, name: Option<&str>, location: &Location<'static>) -> Span {
+    tracing::debug_span!(
+        target: "async_executor",
+        "task",
+        task.name = name,
+        task.id = id,
+        loc.file = location.file(),
+        loc.line = location.line(),
+        loc.col = location.column(),
+    )
+}
+
+/// Creates the span of a new task, the way `console-subscriber` expects spawned tasks.
+#[cfg(feature = "console")]
+pub(crate) fn task_span(id: u64, name: Option<&str>, location: &Location<'static>) -> Span {
+    tracing::trace_span!(
+        target: "tokio::task",
+        "runtime.spawn",
+        kind = "task",
+        task.name = name,
+        task.id = id,
+        loc.file = location.file(),
+        loc.line = location.line(),
+        loc.col = location.column(),
+    )
+}
+
+/// Enters the task's span and a child span for every poll of `future`.
+#[cfg(feature = "tracing")]
+pub(crate) async fn instrument<T>(span: Span, future: impl Future<Output = T>) -> T {
+    #[cfg(feature = "console")]
+    let mut wakers = crate::console::TaskWakers::new(&span);
+
+    futures_lite::pin!(future);
+    future::poll_fn(|cx| {
+        let _task = span.enter();
+        let _poll = tracing::trace_span!(target: "async_executor", "poll").entered();
+
+        // Report what the task does with its waker.
+        #[cfg(feature = "console")]
+        if let Some(waker) = wakers.get(cx.waker()) {
+            return future.as_mut().poll(&mut Context::from_waker(&waker));
+        }
+        future.as_mut().poll(cx)
+    })
+    .await
+}
@@ -0,0 +1,232 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use slab::Slab;
+
+use crate::atomic::AtomicU64;
+use crate::nanos_since;
+
+/// How often a task has been woken.
+///
+/// Collected by executors configured with [`Builder::track_wakes()`][crate::Builder::track_wakes].
+///
+/// # Examples
+///
+/// ```
+/// use async_executor::Builder;
+///
+/// let ex = Builder::new().track_wakes(true).build();
+///
+/// let task = ex.spawn(async {});
+/// for wakes in ex.task_wakes() {
+///     println!("task {} was woken {} times", wakes.id, wakes.wakes);
+/// }
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TaskWakes {
+    /// The ID of the task, unique within its executor.
+    pub id: u64,
+
+    /// Number of times the task was woken.
+    pub wakes: u64,
+
+    /// Number of times the task was woken while it was being polled, for example by yielding.
+    pub self_wakes: u64,
+
+    /// How long the task has been waiting to be woken since its last poll.
+    ///
+    /// `None` if the task is being polled, is scheduled to be polled, or was never polled.
+    pub waiting: Option<Duration>,
+}
+
+/// Wake counters of one task.
+#[derive(Debug)]
+struct WakeTracker {
+    id: u64,
+    wakes: AtomicU64,
+    self_wakes: AtomicU64,
+
+    /// Whether the task is being polled.
+    running: AtomicBool,
+
+    /// Whether the task has completed or was cancelled.
+    completed: AtomicBool,
+
+    /// When the last poll started, in nanoseconds since the epoch plus one, or 0 if never polled.
+    polled_at: AtomicU64,
+
+    /// When the last poll ended, in nanoseconds since the epoch.
+    idle_since: AtomicU64,
+
+    /// When the task was last woken, in nanoseconds since the epoch plus one.
+    woken_at: AtomicU64,
+
+    /// The instant timestamps are measured from.
+    epoch: Instant,
+
+    /// Counter of wakes after completion, shared by all tasks of an executor.
+    after_completion: Arc<AtomicU64>,
+}
+
+impl WakeTracker {
+    /// Returns the counters so far.
+    fn wakes(&self) -> TaskWakes {
+        let polled_at = self.polled_at.load(Ordering::SeqCst);
+        let waiting = !self.running.load(Ordering::SeqCst)
+            && polled_at != 0
+            && self.woken_at.load(Ordering::SeqCst) < polled_at;
+
+        TaskWakes {
+            id: self.id,
+            wakes: self.wakes.load(Ordering::Relaxed),
+            self_wakes: self.self_wakes.load(Ordering::Relaxed),
+            waiting: waiting.then(|| {
+                let idle_since = self.idle_since.load(Ordering::SeqCst);
+                Duration::from_nanos(nanos_since(self.epoch).saturating_sub(idle_since))
+            }),
+        }
+    }
+}
+
+/// A waker that counts wakes before waking the task.
+#[derive(Debug)]
+struct TrackedWaker {
+    tracker: Arc<WakeTracker>,
+    waker: Waker,
+}
+
+impl Wake for TrackedWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        let tracker = &self.tracker;
+        if tracker.completed.load(Ordering::SeqCst) {
+            tracker.after_completion.fetch_add(1, Ordering::Relaxed);
+            event!(task = tracker.id, "task woken after completion");
+            return;
+        }
+
+        tracker.wakes.fetch_add(1, Ordering::Relaxed);
+        if tracker.running.load(Ordering::SeqCst) {
+            tracker.self_wakes.fetch_add(1, Ordering::Relaxed);
+        }
+        tracker
+            .woken_at
+            .store(nanos_since(tracker.epoch) + 1, Ordering::SeqCst);
+        self.waker.wake_by_ref();
+    }
+}
+
+/// Wake counters of all unfinished tasks of an executor.
+#[derive(Debug)]
+pub(crate) struct Wakes {
+    live: Mutex<Slab<Arc<WakeTracker>>>,
+    epoch: Instant,
+    after_completion: Arc<AtomicU64>,
+}
+
+impl Wakes {
+    /// Creates an empty list of counters, with timestamps measured from `epoch`.
+    pub(crate) fn new(epoch: Instant) -> Wakes {
+        Wakes {
+            live: Mutex::new(Slab::new()),
+            epoch,
+            after_completion: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Creates counters for a new task with the given ID.
+    ///
+    /// The counters are removed from the list of live counters when the returned guard is
+    /// dropped.
+    pub(crate) fn register(self: &Arc<Self>, id: u64) -> WakeGuard {
+        let tracker = Arc::new(WakeTracker {
+            id,
+            wakes: AtomicU64::new(0),
+            self_wakes: AtomicU64::new(0),
+            running: AtomicBool::new(false),
+            completed: AtomicBool::new(false),
+            polled_at: AtomicU64::new(0),
+            idle_since: AtomicU64::new(0),
+            woken_at: AtomicU64::new(0),
+            epoch: self.epoch,
+            after_completion: self.after_completion.clone(),
+        });
+        let key = self.live.lock().insert(tracker.clone());
+
+        WakeGuard {
+            wakes: self.clone(),
+            key,
+            tracker,
+            waker: None,
+        }
+    }
+
+    /// Returns the counters of all unfinished tasks.
+    pub(crate) fn snapshot(&self) -> Vec<TaskWakes> {
+        self.live.lock().iter().map(|(_, t)| t.wakes()).collect()
+    }
+
+    /// Returns the number of times tasks were woken after they had completed.
+    pub(crate) fn after_completion(&self) -> u64 {
+        self.after_completion.load(Ordering::Relaxed)
+    }
+}
+
+/// A task's wake counters, registered in the list of live counters.
+#[derive(Debug)]
+pub(crate) struct WakeGuard {
+    wakes: Arc<Wakes>,
+    key: usize,
+    tracker: Arc<WakeTracker>,
+
+    /// The task's waker and the waker that counts wakes before waking it.
+    waker: Option<(Waker, Waker)>,
+}
+
+impl WakeGuard {
+    /// Runs one poll of the task with a waker that counts wakes.
+    pub(crate) fn poll<T>(
+        &mut self,
+        cx: &mut Context<'_>,
+        poll: impl FnOnce(&mut Context<'_>) -> Poll<T>,
+    ) -> Poll<T> {
+        // Reuse the counting waker as long as the task's waker stays the same.
+        let waker = match &self.waker {
+            Some((waker, tracked)) if waker.will_wake(cx.waker()) => tracked,
+            _ => {
+                let tracked = Waker::from(Arc::new(TrackedWaker {
+                    tracker: self.tracker.clone(),
+                    waker: cx.waker().clone(),
+                }));
+                &self.waker.insert((cx.waker().clone(), tracked)).1
+            }
+        };
+
+        let tracker = &self.tracker;
+        tracker.running.store(true, Ordering::SeqCst);
+        tracker
+            .polled_at
+            .store(nanos_since(tracker.epoch) + 1, Ordering::SeqCst);
+
+        let output = poll(&mut Context::from_waker(waker));
+
+        tracker
+            .idle_since
+            .store(nanos_since(tracker.epoch), Ordering::SeqCst);
+        tracker.running.store(false, Ordering::SeqCst);
+        output
+    }
+}
+
+impl Drop for WakeGuard {
+    fn drop(&mut self) {
+        self.tracker.completed.store(true, Ordering::SeqCst);
+        self.wakes.live.lock().try_remove(self.key);
+    }
+}
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use async_executor::{Actor, Executor, Reply, TrySendError};
+use easy_parallel::Parallel;
+use futures_lite::future;
+
+enum Message {
+    Add(u64),
+    Get(Reply<u64>),
+    Ignore(Reply<u64>),
+    Panic,
+}
+
+struct Counter {
+    sum: u64,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Counter {
+    fn new() -> (Counter, Arc<AtomicBool>) {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let counter = Counter {
+            sum: 0,
+            stopped: stopped.clone(),
+        };
+        (counter, stopped)
+    }
+}
+
+impl Actor for Counter {
+    type Message = Message;
+
+    async fn handle(&mut self, message: Message) {
+        match message {
+            Message::Add(n) => self.sum += n,
+            Message::Get(reply) => reply.send(self.sum),
+            Message::Ignore(reply) => drop(reply),
+            Message::Panic => panic!("actor panicked"),
+        }
+    }
+
+    async fn stopped(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn full_mailbox_makes_senders_wait() {
+    let ex = Executor::new();
+    let (counter, _) = Counter::new();
+    let addr = ex.spawn_actor(counter, 2);
+
+    addr.try_send(Message::Add(1)).unwrap();
+    addr.try_send(Message::Add(2)).unwrap();
+    assert!(matches!(
+        addr.try_send(Message::Add(3)),
+        Err(TrySendError::Full(_))
+    ));
+
+    let mut send = Box::pin(addr.send(Message::Add(3)));
+    assert!(future::block_on(future::poll_once(&mut send)).is_none());
+    let metrics = addr.metrics();
+    assert_eq!(metrics.len, 2);
+    assert_eq!(metrics.capacity, 2);
+    assert_eq!(metrics.waiting_senders, 1);
+
+    // The actor makes room by handling messages.
+    ex.block_on(async {
+        send.await.unwrap();
+        assert_eq!(addr.ask(Message::Get).await, Some(6));
+    });
+    let metrics = addr.metrics();
+    assert_eq!(metrics.peak_len, 2);
+    assert_eq!(metrics.received, 4);
+    assert_eq!(metrics.waiting_senders, 0);
+}
+
+#[test]
+fn stops_after_last_addr_is_dropped() {
+    let ex = Executor::new();
+    let (counter, stopped) = Counter::new();
+    let addr = ex.spawn_actor(counter, 16);
+    let other = addr.clone();
+
+    addr.try_send(Message::Add(1)).unwrap();
+    drop(addr);
+    while ex.try_tick() {}
+    assert!(!stopped.load(Ordering::SeqCst));
+    assert_eq!(ex.detached_tasks(), [other.id()]);
+
+    // Messages sent before the last address is dropped are still handled.
+    other.try_send(Message::Add(2)).unwrap();
+    drop(other);
+    while ex.try_tick() {}
+    assert!(stopped.load(Ordering::SeqCst));
+    assert!(ex.detached_tasks().is_empty());
+}
+
+#[test]
+fn ask_fails_without_answer() {
+    let ex = Executor::new();
+    let (counter, stopped) = Counter::new();
+    let addr = ex.spawn_actor(counter, 16);
+
+    assert_eq!(ex.block_on(addr.ask(Message::Ignore)), None);
+
+    // After a panic, the actor doesn't accept messages anymore.
+    addr.try_send(Message::Panic).unwrap();
+    addr.try_send(Message::Add(1)).unwrap();
+    while ex.try_tick() {}
+    assert!(addr.is_stopped());
+    assert!(!stopped.load(Ordering::SeqCst));
+    assert_eq!(ex.failed_tasks(), 1);
+    assert_eq!(ex.block_on(addr.ask(Message::Get)), None);
+}
+
+#[test]
+fn addrs_are_shared_between_threads() {
+    let ex = Executor::new();
+    let (counter, _) = Counter::new();
+    let addr = ex.spawn_actor(counter, 4);
+
+    Parallel::new()
+        .each(0..4, |_| {
+            ex.block_on(async {
+                for _ in 0..100 {
+                    addr.send(Message::Add(1)).await.unwrap();
+                }
+            })
+        })
+        .run();
+    assert_eq!(ex.block_on(addr.ask(Message::Get)), Some(400));
+}
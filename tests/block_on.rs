@@ -0,0 +1,56 @@
+use std::thread;
+use std::time::Duration;
+
+use async_executor::Executor;
+use easy_parallel::Parallel;
+
+#[test]
+fn block_on_runs_tasks_scheduled_from_other_threads() {
+    let ex = Executor::new();
+    let (s, r) = async_channel::unbounded::<usize>();
+
+    // The tasks are spawned while the main thread is parked.
+    Parallel::new()
+        .add(|| {
+            for i in 0..10 {
+                thread::sleep(Duration::from_millis(1));
+                let s = s.clone();
+                ex.spawn(async move { s.send(i).await.unwrap() }).detach();
+            }
+        })
+        .finish(|| {
+            let sum = ex.block_on(async {
+                let mut sum = 0;
+                for _ in 0..10 {
+                    sum += r.recv().await.unwrap();
+                }
+                sum
+            });
+            assert_eq!(sum, 45);
+        });
+}
+
+#[test]
+fn block_on_wakes_main_future() {
+    let ex = Executor::new();
+    let (s, r) = async_channel::bounded::<()>(1);
+
+    // Nothing else runs on the executor, so only the main future's waker unparks the thread.
+    let handle = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(10));
+        s.send_blocking(()).unwrap();
+    });
+    ex.block_on(r.recv()).unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn run_forever_serves_other_threads() {
+    static EX: Executor<'_> = Executor::new();
+
+    thread::spawn(|| EX.run_forever());
+
+    // Tasks complete even though this thread never runs the executor.
+    let task = EX.spawn(async { 7 });
+    assert_eq!(futures_lite::future::block_on(task), 7);
+}
@@ -0,0 +1,135 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_executor::{Builder, CancellationToken, Cancelled, Executor};
+use async_io::Timer;
+use futures_lite::future;
+
+#[test]
+fn tasks_get_a_grace_period() {
+    let ex = Builder::new()
+        .cancel_grace_period(Duration::from_millis(50))
+        .build();
+
+    // This task winds down within the grace period.
+    let token = CancellationToken::new();
+    let polite = ex.spawn_with_token(token.clone(), async {
+        CancellationToken::current().unwrap().cancelled().await;
+        "done"
+    });
+
+    // This one ignores the token and gets dropped.
+    let dropped = Arc::new(AtomicBool::new(false));
+    let rude = ex.spawn_with_token(token.clone(), {
+        let dropped = dropped.clone();
+        async move {
+            let _guard = scopeguard::guard((), |_| dropped.store(true, Ordering::SeqCst));
+            future::pending::<()>().await
+        }
+    });
+
+    let start = Instant::now();
+    token.cancel();
+    assert_eq!(ex.block_on(polite), Ok("done"));
+    assert!(!dropped.load(Ordering::SeqCst));
+    assert_eq!(ex.block_on(rude), Err(Cancelled));
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert!(dropped.load(Ordering::SeqCst));
+}
+
+#[test]
+fn spawned_tasks_inherit_the_token() {
+    static EX: Executor<'_> = Builder::new()
+        .cancel_grace_period(Duration::from_secs(10))
+        .build();
+    let token = CancellationToken::new();
+
+    let parent = EX.spawn_with_token(token.clone(), async {
+        // The child token gets cancelled together with the parent's.
+        let child = EX.spawn(async {
+            let token = CancellationToken::current().unwrap();
+            token.cancelled().await;
+        });
+        CancellationToken::current().unwrap().cancelled().await;
+        child.await;
+    });
+
+    EX.block_on(async {
+        for _ in 0..10 {
+            future::yield_now().await;
+        }
+        token.cancel();
+        assert_eq!(parent.await, Ok(()));
+    });
+
+    // Tasks spawned outside a task with a token don't get one.
+    let task = EX.spawn(async { CancellationToken::current().is_none() });
+    assert!(EX.block_on(task));
+}
+
+#[test]
+fn spawned_tasks_are_dropped_with_the_parent() {
+    static EX: Executor<'_> = Builder::new()
+        .cancel_grace_period(Duration::from_millis(50))
+        .build();
+    let token = CancellationToken::new();
+    let (s, r) = async_channel::bounded::<()>(1);
+
+    // The parent winds down, but the child it spawns ignores the token.
+    let parent = EX.spawn_with_token(token.clone(), async {
+        EX.spawn(async move {
+            let _s = s;
+            future::pending::<()>().await
+        })
+        .detach();
+        CancellationToken::current().unwrap().cancelled().await;
+    });
+
+    let start = Instant::now();
+    token.cancel();
+    assert_eq!(EX.block_on(parent), Ok(()));
+
+    // The channel gets closed when the child is dropped after the grace period.
+    assert!(EX.block_on(r.recv()).is_err());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+fn timeouts_dont_cancel_the_parent() {
+    static EX: Executor<'_> = Executor::new();
+    let token = CancellationToken::new();
+
+    let parent = EX.spawn_with_token(token.clone(), async {
+        let child = EX.spawn_with_timeout(Duration::from_millis(10), future::pending::<()>());
+        assert_eq!(child.await, Err(Cancelled));
+        CancellationToken::current().unwrap().is_cancelled()
+    });
+    assert_eq!(EX.block_on(parent), Ok(false));
+    assert!(!token.is_cancelled());
+}
+
+#[test]
+fn timers_use_the_sleep_hook() {
+    let deadlines = Arc::new(Mutex::new(Vec::new()));
+    let ex = Builder::new()
+        .cancel_grace_period(Duration::from_millis(20))
+        .sleep({
+            let deadlines = deadlines.clone();
+            move |deadline| {
+                deadlines.lock().unwrap().push(deadline);
+                Timer::at(deadline)
+            }
+        })
+        .build();
+
+    let start = Instant::now();
+    let task = ex.spawn_with_timeout(Duration::from_millis(10), future::pending::<()>());
+    assert_eq!(ex.block_on(task), Err(Cancelled));
+
+    // One sleep for the timeout, and one for the grace period after it.
+    let deadlines = deadlines.lock().unwrap();
+    assert_eq!(deadlines.len(), 2);
+    assert!(deadlines[0] >= start + Duration::from_millis(10));
+    assert!(deadlines[1] >= deadlines[0] + Duration::from_millis(20));
+}
@@ -0,0 +1,150 @@
+#![cfg(feature = "console")]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_executor::Executor;
+use futures_lite::future;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// What a console would show about a task.
+#[derive(Debug, Default, Clone)]
+struct TaskStats {
+    name: Option<String>,
+    polls: u64,
+    wakes: u64,
+    wakes_by_ref: u64,
+    clones: u64,
+    drops: u64,
+    closed: bool,
+}
+
+/// A local stand-in for `console-subscriber`, which keeps the stats of tasks in memory.
+#[derive(Default, Clone)]
+struct Console {
+    next_id: Arc<AtomicU64>,
+    tasks: Arc<Mutex<HashMap<u64, TaskStats>>>,
+}
+
+/// Collects string and integer fields.
+#[derive(Default)]
+struct Fields(HashMap<&'static str, String>);
+
+impl Visit for Fields {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Console {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst) + 1;
+        if attrs.metadata().name() == "runtime.spawn" {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+            assert_eq!(fields.0["kind"], "task");
+
+            let stats = TaskStats {
+                name: fields.0.remove("task.name"),
+                ..TaskStats::default()
+            };
+            self.tasks.lock().unwrap().insert(id, stats);
+        }
+        Id::from_u64(id)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        if event.metadata().target() != "runtime::waker" {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+
+        let id = fields.0["task.id"].parse::<u64>().unwrap();
+        let mut tasks = self.tasks.lock().unwrap();
+        let stats = tasks.get_mut(&id).unwrap();
+        match fields.0["op"].as_str() {
+            "waker.wake" => stats.wakes += 1,
+            "waker.wake_by_ref" => stats.wakes_by_ref += 1,
+            "waker.clone" => stats.clones += 1,
+            "waker.drop" => stats.drops += 1,
+            op => panic!("unknown waker op {}", op),
+        }
+    }
+
+    fn enter(&self, span: &Id) {
+        if let Some(stats) = self.tasks.lock().unwrap().get_mut(&span.into_u64()) {
+            stats.polls += 1;
+        }
+    }
+
+    fn exit(&self, _: &Id) {}
+
+    fn try_close(&self, span: Id) -> bool {
+        if let Some(stats) = self.tasks.lock().unwrap().get_mut(&span.into_u64()) {
+            stats.closed = true;
+        }
+        true
+    }
+}
+
+#[test]
+fn console_sees_task_lifecycle() {
+    let console = Console::default();
+
+    tracing::subscriber::with_default(console.clone(), || {
+        let ex = Executor::new();
+        let (s, r) = async_channel::bounded::<()>(1);
+        let task = ex
+            .group("recv")
+            .spawn(async move { r.recv().await.unwrap() });
+
+        // The task stores a clone of its waker while waiting for a message.
+        assert!(ex.try_tick());
+        let stats = console
+            .tasks
+            .lock()
+            .unwrap()
+            .values()
+            .next()
+            .unwrap()
+            .clone();
+        assert_eq!(stats.polls, 1);
+        assert_eq!(stats.clones - stats.drops, 1);
+
+        s.try_send(()).unwrap();
+        future::block_on(ex.run(task));
+    });
+
+    let tasks = console.tasks.lock().unwrap();
+    assert_eq!(tasks.len(), 1);
+
+    let stats = tasks.values().next().unwrap();
+    assert_eq!(stats.name.as_deref(), Some("recv"));
+    assert_eq!(stats.polls, 2);
+    assert_eq!(stats.wakes + stats.wakes_by_ref, 1);
+    assert!(stats.closed);
+
+    // Every clone of the waker is gone by the time the task completes.
+    assert_eq!(stats.clones, stats.drops + stats.wakes);
+}
@@ -0,0 +1,47 @@
+use std::sync::{Arc, Mutex};
+
+use async_executor::{Builder, TaskFailure};
+
+#[test]
+fn failures_are_reported() {
+    static FAILURES: Mutex<Vec<(u64, String, bool)>> = Mutex::new(Vec::new());
+
+    fn report(failure: &TaskFailure) {
+        let error = failure.error.to_string();
+        FAILURES
+            .lock()
+            .unwrap()
+            .push((failure.id, error, failure.panicked));
+    }
+
+    let ex = Builder::new().on_task_failure(Arc::new(report)).build();
+    let (s, r) = async_channel::bounded::<()>(1);
+
+    let ok = ex.spawn_detached(async { Ok::<(), String>(()) });
+    let failed = ex.spawn_detached(async { Err("oops") });
+    let panicked = ex.spawn_detached(async {
+        if true {
+            panic!("boom");
+        }
+        Ok::<(), String>(())
+    });
+    let waiting = ex.spawn_detached(async move { r.recv().await.map_err(|e| e.to_string()) });
+    assert_eq!(ex.detached_tasks(), [ok, failed, panicked, waiting]);
+
+    // The panic doesn't reach the runner.
+    while ex.try_tick() {}
+    assert_eq!(ex.detached_tasks(), [waiting]);
+    assert_eq!(ex.failed_tasks(), 2);
+    assert_eq!(
+        *FAILURES.lock().unwrap(),
+        [
+            (failed, "oops".to_string(), false),
+            (panicked, "boom".to_string(), true),
+        ]
+    );
+
+    s.try_send(()).unwrap();
+    while ex.try_tick() {}
+    assert!(ex.detached_tasks().is_empty());
+    assert_eq!(ex.failed_tasks(), 2);
+}
@@ -4,14 +4,14 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Mutex;
 use std::task::{Poll, Waker};
 
-use async_executor::{Executor, Task};
+use async_executor::{Builder, Executor, Task};
 use futures_lite::future;
 use once_cell::sync::Lazy;
 
 #[test]
 fn executor_cancels_everything() {
     static DROP: AtomicUsize = AtomicUsize::new(0);
-    static WAKER: Lazy<Mutex<Option<Waker>>> = Lazy::new(|| Default::default());
+    static WAKER: Lazy<Mutex<Option<Waker>>> = Lazy::new(Default::default);
 
     let ex = Executor::new();
 
@@ -38,10 +38,46 @@ fn executor_cancels_everything() {
     assert_eq!(DROP.load(Ordering::SeqCst), 1);
 }
 
+#[test]
+fn executor_cancels_tasks_spawned_from_many_threads() {
+    static DROP: AtomicUsize = AtomicUsize::new(0);
+
+    let ex = Executor::new();
+
+    let tasks: Vec<Task<()>> = easy_parallel::Parallel::new()
+        .each(0..8, |_| {
+            (0..100)
+                .map(|_| {
+                    ex.spawn(async {
+                        let _guard = CallOnDrop(|| {
+                            DROP.fetch_add(1, Ordering::SeqCst);
+                        });
+                        future::pending::<()>().await;
+                    })
+                })
+                .collect::<Vec<_>>()
+        })
+        .run()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    while ex.try_tick() {}
+    assert!(!ex.is_empty());
+    assert_eq!(DROP.load(Ordering::SeqCst), 0);
+
+    drop(ex);
+    assert_eq!(DROP.load(Ordering::SeqCst), 800);
+
+    for task in tasks {
+        assert!(catch_unwind(|| future::block_on(task)).is_err());
+    }
+}
+
 #[test]
 fn leaked_executor_leaks_everything() {
     static DROP: AtomicUsize = AtomicUsize::new(0);
-    static WAKER: Lazy<Mutex<Option<Waker>>> = Lazy::new(|| Default::default());
+    static WAKER: Lazy<Mutex<Option<Waker>>> = Lazy::new(Default::default);
 
     let ex = Executor::new();
 
@@ -68,6 +104,57 @@ fn leaked_executor_leaks_everything() {
     assert_eq!(DROP.load(Ordering::SeqCst), 0);
 }
 
+#[test]
+fn untracked_executor_cancels_scheduled_tasks() {
+    static DROP: AtomicUsize = AtomicUsize::new(0);
+
+    let ex = Builder::new().track_tasks(false).build();
+
+    let guard = CallOnDrop(|| {
+        DROP.fetch_add(1, Ordering::SeqCst);
+    });
+    let task = ex.spawn(async move {
+        let _guard = guard;
+    });
+    assert!(!ex.is_empty());
+
+    drop(ex);
+    assert_eq!(DROP.load(Ordering::SeqCst), 1);
+
+    assert!(catch_unwind(|| future::block_on(task)).is_err());
+}
+
+#[test]
+fn untracked_executor_leaks_idle_tasks() {
+    static DROP: AtomicUsize = AtomicUsize::new(0);
+    static WAKER: Lazy<Mutex<Option<Waker>>> = Lazy::new(Default::default);
+
+    let ex = Builder::new().track_tasks(false).build();
+
+    let task = ex.spawn(async {
+        let _guard = CallOnDrop(|| {
+            DROP.fetch_add(1, Ordering::SeqCst);
+        });
+
+        future::poll_fn(|cx| {
+            *WAKER.lock().unwrap() = Some(cx.waker().clone());
+            Poll::Pending::<()>
+        })
+        .await;
+    });
+
+    future::block_on(ex.tick());
+    assert!(WAKER.lock().unwrap().is_some());
+
+    drop(ex);
+    assert_eq!(DROP.load(Ordering::SeqCst), 0);
+
+    // Waking the task after the executor is gone never runs nor drops it.
+    WAKER.lock().unwrap().take().unwrap().wake();
+    assert!(future::block_on(future::poll_once(task)).is_none());
+    assert_eq!(DROP.load(Ordering::SeqCst), 0);
+}
+
 #[test]
 fn await_task_after_dropping_executor() {
     let s: String = "hello".into();
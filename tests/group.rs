@@ -0,0 +1,90 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_executor::Executor;
+use futures_lite::future;
+
+/// Keeps the thread busy for a while.
+fn spin(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {}
+}
+
+#[test]
+fn groups_share_time_by_weight() {
+    let ex = Executor::new();
+    let heavy = ex.group("heavy");
+    let light = ex.group("light");
+    heavy.set_weight(3);
+
+    // Time spent polling each group, measured by the tasks themselves rather than the executor.
+    let busy = Arc::new([AtomicU64::new(0), AtomicU64::new(0)]);
+    let mut tasks = Vec::new();
+    for (i, group) in [&heavy, &light].iter().enumerate() {
+        for _ in 0..4 {
+            let busy = busy.clone();
+            tasks.push(group.spawn(async move {
+                loop {
+                    let start = Instant::now();
+                    spin(Duration::from_micros(50));
+                    busy[i].fetch_add(start.elapsed().as_nanos() as u64, Ordering::SeqCst);
+                    future::yield_now().await;
+                }
+            }));
+        }
+    }
+
+    for _ in 0..800 {
+        assert!(ex.try_tick());
+    }
+
+    let heavy_busy = busy[0].load(Ordering::SeqCst) as f64;
+    let light_busy = busy[1].load(Ordering::SeqCst) as f64;
+    let ratio = heavy_busy / light_busy;
+    assert!((2.0..4.5).contains(&ratio), "ratio = {}", ratio);
+}
+
+#[test]
+fn cancel_drops_all_tasks_in_group() {
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct CountDrop;
+
+    impl Drop for CountDrop {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let ex = Executor::new();
+    let group = ex.group("cancelled");
+    group.set_max_tasks(Some(2));
+
+    // Some tasks get polled and wait, some stay scheduled, and some wait for a slot.
+    let tasks: Vec<_> = (0..5)
+        .map(|_| {
+            let guard = CountDrop;
+            group.spawn(async move {
+                let _guard = guard;
+                future::pending::<()>().await
+            })
+        })
+        .collect();
+    assert!(ex.try_tick());
+    let other = ex.spawn(async { 7 });
+
+    group.cancel();
+    while ex.try_tick() {}
+    assert_eq!(DROPS.load(Ordering::SeqCst), 5);
+    for task in tasks {
+        assert!(future::block_on(task.cancel()).is_none());
+    }
+    assert_eq!(future::block_on(other), 7);
+
+    // The group is still usable.
+    let task = group.spawn(async { 1 });
+    assert!(ex.try_tick());
+    assert_eq!(future::block_on(task), 1);
+    assert!(ex.is_empty());
+}
@@ -0,0 +1,72 @@
+use std::sync::Barrier;
+use std::thread;
+use std::time::Duration;
+
+use async_executor::{Builder, Executor};
+use easy_parallel::Parallel;
+use futures_lite::future;
+
+#[test]
+fn queueing_delay_is_recorded() {
+    let ex = Builder::new().record_latency(true).build();
+
+    let task = ex.spawn(async {});
+    thread::sleep(Duration::from_millis(20));
+    assert!(ex.try_tick());
+    future::block_on(task);
+
+    let histogram = ex.latency_histogram();
+    assert_eq!(histogram.count(), 1);
+    // The task waited for at least the sleep, give or take the precision of the histogram.
+    assert!(histogram.min() >= Duration::from_millis(10));
+    assert!(histogram.min() <= histogram.quantile(0.5));
+    assert!(histogram.quantile(0.5) <= histogram.max());
+    assert!(ex.runner_latency_histograms().is_empty());
+}
+
+#[test]
+fn every_poll_is_recorded() {
+    let ex = Builder::new().record_latency(true).build();
+
+    let task = ex.spawn(async {
+        for _ in 0..9 {
+            future::yield_now().await;
+        }
+    });
+    future::block_on(ex.run(task));
+    assert_eq!(ex.latency_histogram().count(), 10);
+
+    // Nothing is recorded by default.
+    let ex = Executor::new();
+    future::block_on(ex.run(ex.spawn(async {})));
+    assert_eq!(ex.latency_histogram().count(), 0);
+}
+
+#[test]
+fn runners_record_their_own_latency() {
+    let ex = Builder::new().record_runner_latency(true).build();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let started = Barrier::new(3);
+
+    Parallel::new()
+        .each(0..2, |_| {
+            future::block_on(ex.run(async {
+                started.wait();
+                shutdown.recv().await
+            }))
+        })
+        .finish(|| {
+            started.wait();
+            let tasks: Vec<_> = (0..100).map(|i| ex.spawn(async move { i })).collect();
+            for task in tasks {
+                future::block_on(task);
+            }
+
+            let runners = ex.runner_latency_histograms();
+            assert_eq!(runners.len(), 2);
+            let polls: u64 = runners.iter().map(|h| h.count()).sum();
+            assert_eq!(polls, ex.latency_histogram().count());
+            assert_eq!(polls, 100);
+            drop(signal);
+        });
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_executor::Executor;
+use futures_lite::future;
+
+#[test]
+fn queued_tasks_are_not_polled() {
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+    let ex = Executor::new();
+    ex.set_max_tasks(Some(2));
+
+    let (s, r) = async_channel::unbounded::<()>();
+    let tasks: Vec<_> = (0..5)
+        .map(|_| {
+            let r = r.clone();
+            ex.spawn(async move {
+                POLLS.fetch_add(1, Ordering::SeqCst);
+                r.recv().await.ok();
+            })
+        })
+        .collect();
+
+    while ex.try_tick() {}
+    assert_eq!(POLLS.load(Ordering::SeqCst), 2);
+
+    // Each completed task frees a slot for exactly one queued task.
+    s.try_send(()).unwrap();
+    while ex.try_tick() {}
+    assert_eq!(POLLS.load(Ordering::SeqCst), 3);
+
+    drop(s);
+    future::block_on(ex.run(async {
+        for task in tasks {
+            task.await;
+        }
+    }));
+    assert_eq!(POLLS.load(Ordering::SeqCst), 5);
+    assert!(ex.is_empty());
+}
+
+#[test]
+fn cancelled_task_frees_slot() {
+    let ex = Executor::new();
+    ex.set_max_tasks(Some(1));
+
+    let t1 = ex.spawn(future::pending::<()>());
+    let t2 = ex.spawn(async { 7 });
+    assert!(ex.try_tick());
+    assert!(!ex.try_tick());
+
+    drop(t1);
+    assert_eq!(future::block_on(ex.run(t2)), 7);
+    assert!(ex.is_empty());
+}
+
+#[test]
+fn raising_limit_admits_queued_tasks() {
+    let ex = Executor::new();
+    ex.set_max_tasks(Some(0));
+
+    let task = ex.spawn(async { 1 });
+    assert!(!ex.try_tick());
+
+    ex.set_max_tasks(None);
+    assert_eq!(future::block_on(ex.run(task)), 1);
+}
+
+#[test]
+fn group_limit_is_separate() {
+    static POLLS: AtomicUsize = AtomicUsize::new(0);
+
+    let ex = Executor::new();
+    let group = ex.group("limited");
+    group.set_max_tasks(Some(3));
+
+    let (s, r) = async_channel::unbounded::<()>();
+    let tasks: Vec<_> = (0..20)
+        .map(|_| {
+            let r = r.clone();
+            group.spawn(async move {
+                POLLS.fetch_add(1, Ordering::SeqCst);
+                r.recv().await.ok();
+            })
+        })
+        .collect();
+    let other = ex.spawn(async { 1 });
+
+    while ex.try_tick() {}
+    assert_eq!(POLLS.load(Ordering::SeqCst), 3);
+    assert_eq!(future::block_on(other), 1);
+
+    drop(s);
+    future::block_on(ex.run(async {
+        for task in tasks {
+            task.await;
+        }
+    }));
+    assert_eq!(POLLS.load(Ordering::SeqCst), 20);
+}
+
+#[test]
+fn drop_executor_with_queued_tasks() {
+    let ex = Executor::new();
+    ex.set_max_tasks(Some(1));
+
+    let group = ex.group("limited");
+    group.set_max_tasks(Some(1));
+
+    let running = ex.spawn(future::pending::<()>());
+    let queued = ex.spawn(async {});
+    let group_queued = group.spawn(async {});
+    let group_queued2 = group.spawn(async {});
+    assert!(ex.try_tick());
+
+    drop(ex);
+    for task in [running, queued, group_queued, group_queued2] {
+        assert!(future::block_on(task.cancel()).is_none());
+    }
+}
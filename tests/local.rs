@@ -0,0 +1,129 @@
+use std::cell::Cell;
+use std::panic::catch_unwind;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+
+use async_executor::{spawn_local, LocalExecutor};
+use futures_lite::future;
+
+#[test]
+fn wakes_from_other_threads_reach_local_executor() {
+    let ex = LocalExecutor::new();
+    let (s, r) = async_channel::bounded::<usize>(1);
+
+    // Not `Send`, so the task can only run on this thread.
+    let sum = Rc::new(Cell::new(0));
+    let task = ex.spawn({
+        let sum = sum.clone();
+        async move {
+            while let Ok(n) = r.recv().await {
+                sum.set(sum.get() + n);
+            }
+        }
+    });
+
+    let sender = thread::spawn(move || {
+        for n in 0..100 {
+            if n % 20 == 0 {
+                thread::sleep(Duration::from_millis(1));
+            }
+            future::block_on(s.send(n)).unwrap();
+        }
+    });
+
+    future::block_on(ex.run(task));
+    sender.join().unwrap();
+    assert_eq!(sum.get(), (0..100).sum());
+}
+
+#[test]
+fn dropping_executor_cancels_tasks() {
+    let ex = LocalExecutor::new();
+    let (s, r) = async_channel::bounded::<()>(1);
+
+    let dropped = Rc::new(Cell::new(0));
+    for _ in 0..10 {
+        let dropped = dropped.clone();
+        let r = r.clone();
+        ex.spawn(async move {
+            let _guard = CallOnDrop(move || dropped.set(dropped.get() + 1));
+            r.recv().await.ok();
+        })
+        .detach();
+    }
+    while ex.try_tick() {}
+    assert_eq!(dropped.get(), 0);
+
+    // Wake some of the tasks from another thread before dropping the executor.
+    thread::spawn(move || {
+        s.try_send(()).unwrap();
+    })
+    .join()
+    .unwrap();
+
+    drop(ex);
+    assert_eq!(dropped.get(), 10);
+}
+
+#[test]
+fn task_cancelled_on_other_thread_is_dropped_on_this_thread() {
+    let ex = LocalExecutor::new();
+
+    let dropped = Rc::new(Cell::new(false));
+    let task = ex.spawn({
+        let dropped = dropped.clone();
+        async move {
+            let _guard = CallOnDrop(move || dropped.set(true));
+            future::pending::<()>().await;
+        }
+    });
+    assert!(ex.try_tick());
+
+    thread::spawn(move || drop(task)).join().unwrap();
+    assert!(!dropped.get());
+
+    while ex.try_tick() {}
+    assert!(dropped.get());
+    assert!(ex.is_empty());
+}
+
+#[test]
+fn spawn_local_uses_innermost_executor() {
+    let outer = LocalExecutor::new();
+
+    let task = outer.spawn(async {
+        let inner = LocalExecutor::new();
+        let nested = inner.spawn(async {
+            // Lands on the inner executor, which is the one running this task.
+            spawn_local(async { 1 }).await
+        });
+        let n = future::block_on(inner.run(nested));
+
+        // Back on the outer executor.
+        let task = spawn_local(async move { n + 1 });
+        assert!(inner.is_empty());
+        task.await
+    });
+
+    assert_eq!(future::block_on(outer.run(task)), 2);
+}
+
+#[test]
+fn spawn_local_outside_task_panics() {
+    assert!(catch_unwind(|| spawn_local(async {})).is_err());
+
+    // Running the executor doesn't count, only its tasks do.
+    let ex = LocalExecutor::new();
+    future::block_on(ex.run(async {
+        assert!(catch_unwind(|| spawn_local(async {})).is_err());
+    }));
+}
+
+struct CallOnDrop<F: FnMut()>(F);
+
+impl<F: FnMut()> Drop for CallOnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)();
+    }
+}
@@ -0,0 +1,75 @@
+use std::thread;
+use std::time::Duration;
+
+use async_executor::Executor;
+use easy_parallel::Parallel;
+use futures_lite::future;
+
+#[test]
+fn spawns_wake_sleeping_runners() {
+    let ex = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+
+    Parallel::new()
+        .each(0..8, |_| future::block_on(ex.run(shutdown.recv())))
+        .finish(|| {
+            for i in 0..200 {
+                // Give the runners a chance to fall asleep every now and then.
+                if i % 20 == 0 {
+                    thread::sleep(Duration::from_millis(1));
+                }
+
+                let tasks: Vec<_> = (0..8).map(|j| ex.spawn(async move { i * j })).collect();
+                for (j, task) in tasks.into_iter().enumerate() {
+                    assert_eq!(future::block_on(task), i * j);
+                }
+            }
+            drop(signal);
+        });
+}
+
+#[test]
+fn wakes_from_other_threads_reach_sleeping_runners() {
+    let ex = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (s, r) = async_channel::bounded::<usize>(1);
+
+    Parallel::new()
+        .each(0..4, |_| future::block_on(ex.run(shutdown.recv())))
+        .finish(|| {
+            let task = ex.spawn(async move {
+                let mut sum = 0;
+                while let Ok(n) = r.recv().await {
+                    sum += n;
+                }
+                sum
+            });
+            for n in 0..1000 {
+                future::block_on(s.send(n)).unwrap();
+            }
+            drop(s);
+            assert_eq!(future::block_on(task), (0..1000).sum());
+            drop(signal);
+        });
+}
+
+#[test]
+fn burst_spawned_inside_task_completes() {
+    static EX: Executor<'_> = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+
+    Parallel::new()
+        .each(0..8, |_| future::block_on(EX.run(shutdown.recv())))
+        .finish(|| {
+            let task = EX.spawn(async {
+                let tasks: Vec<_> = (0..1000).map(|i| EX.spawn(async move { i })).collect();
+                let mut sum = 0;
+                for task in tasks {
+                    sum += task.await;
+                }
+                sum
+            });
+            assert_eq!(future::block_on(task), (0..1000).sum());
+            drop(signal);
+        });
+}
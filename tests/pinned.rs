@@ -0,0 +1,145 @@
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+
+use async_executor::{spawn_local, Executor};
+use easy_parallel::Parallel;
+use futures_lite::future;
+
+#[test]
+fn pinned_tasks_stay_on_their_worker() {
+    let ex = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (ready, started) = async_channel::unbounded::<()>();
+
+    Parallel::new()
+        .each(0..4, |_| {
+            future::block_on(ex.run(async {
+                ready.send(()).await.unwrap();
+                shutdown.recv().await.ok();
+            }))
+        })
+        .finish(|| {
+            for _ in 0..4 {
+                future::block_on(started.recv()).unwrap();
+            }
+            let workers = ex.worker_ids();
+            assert_eq!(workers.len(), 4);
+
+            let tasks: Vec<_> = workers
+                .iter()
+                .flat_map(|&worker| (0..10).map(move |_| worker))
+                .map(|worker| {
+                    let task = ex.spawn_pinned(worker, || async {
+                        // Not `Send`, so it can't be held across an await in a normal task.
+                        let id = Rc::new(thread::current().id());
+                        for _ in 0..10 {
+                            future::yield_now().await;
+                            assert_eq!(*id, thread::current().id());
+                        }
+                        *id
+                    });
+                    (worker, task)
+                })
+                .collect();
+
+            let mut threads: Vec<(usize, ThreadId)> = tasks
+                .into_iter()
+                .map(|(worker, task)| (worker, future::block_on(task)))
+                .collect();
+            threads.sort_by_key(|&(worker, _)| worker);
+            threads.dedup();
+            assert_eq!(threads.len(), 4);
+
+            drop(signal);
+        });
+}
+
+#[test]
+fn stopping_worker_cancels_pinned_tasks() {
+    static DROPPED_ON: Mutex<Option<ThreadId>> = Mutex::new(None);
+
+    let ex = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (ready, started) = async_channel::unbounded::<()>();
+
+    let (threads, ()) = Parallel::new()
+        .add(|| {
+            future::block_on(ex.run(async {
+                ready.send(()).await.unwrap();
+                shutdown.recv().await.ok();
+            }));
+            thread::current().id()
+        })
+        .finish(|| {
+            future::block_on(started.recv()).unwrap();
+            let worker = ex.worker_ids()[0];
+
+            let (s, r) = async_channel::bounded::<()>(1);
+            let task = ex.spawn_pinned(worker, move || async move {
+                let _guard = CallOnDrop(|| {
+                    *DROPPED_ON.lock().unwrap() = Some(thread::current().id());
+                });
+                s.send(()).await.unwrap();
+                future::pending::<()>().await;
+            });
+            future::block_on(r.recv()).unwrap();
+
+            drop(signal);
+            assert!(future::block_on(task.cancel()).is_none());
+        });
+
+    assert_eq!(*DROPPED_ON.lock().unwrap(), Some(threads[0]));
+    assert!(ex.worker_ids().is_empty());
+
+    // Tasks pinned to a worker that doesn't exist are cancelled.
+    let task = ex.spawn_pinned(0, || async {});
+    assert!(future::block_on(task.cancel()).is_none());
+}
+
+#[test]
+fn spawn_local_in_pinned_task() {
+    let ex = Executor::new();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (ready, started) = async_channel::unbounded::<()>();
+
+    let (threads, thread) = Parallel::new()
+        .add(|| {
+            future::block_on(ex.run(async {
+                ready.send(()).await.unwrap();
+                shutdown.recv().await.ok();
+            }));
+            thread::current().id()
+        })
+        .finish(|| {
+            future::block_on(started.recv()).unwrap();
+            let worker = ex.worker_ids()[0];
+
+            let task = ex.spawn_pinned(worker, || async {
+                // The spawned task is pinned to the same worker, so it can hold an `Rc`.
+                let n = Rc::new(1);
+                let child = spawn_local(async move {
+                    future::yield_now().await;
+                    (*n + 2, thread::current().id())
+                });
+                let (n, child_thread) = child.await;
+                assert_eq!(child_thread, thread::current().id());
+                (n, child_thread)
+            });
+            let (n, thread) = future::block_on(task);
+            assert_eq!(n, 3);
+
+            drop(signal);
+            thread
+        });
+
+    assert_eq!(threads, [thread]);
+}
+
+struct CallOnDrop<F: Fn()>(F);
+
+impl<F: Fn()> Drop for CallOnDrop<F> {
+    fn drop(&mut self) {
+        (self.0)();
+    }
+}
@@ -0,0 +1,298 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use async_executor::{Builder, Executor, LocalTaskQueue, QueueKind, Runnable, TaskQueue};
+use easy_parallel::Parallel;
+use futures_lite::future;
+
+#[test]
+fn every_kind_of_queue_runs_tasks() {
+    static EXECUTORS: [Executor<'_>; 4] = [
+        Builder::new().queue(QueueKind::Crossbeam).build(),
+        Builder::new().queue(QueueKind::ConcurrentQueue).build(),
+        Builder::new().queue(QueueKind::Priority).build(),
+        Builder::new().queue(QueueKind::Deadline).build(),
+    ];
+
+    for (kind, ex) in EXECUTORS.iter().enumerate() {
+        let (signal, shutdown) = async_channel::unbounded::<()>();
+
+        Parallel::new()
+            .each(0..4, |_| future::block_on(ex.run(shutdown.recv())))
+            .finish(|| {
+                let task = ex.spawn(async move {
+                    // Enough tasks to overflow local queues.
+                    let tasks: Vec<_> = (0..1000).map(|i| ex.spawn(async move { i })).collect();
+                    let mut sum = 0;
+                    for task in tasks {
+                        sum += task.await;
+                    }
+                    sum
+                });
+                assert_eq!(future::block_on(task), (0..1000).sum(), "{:?}", kind);
+                drop(signal);
+            });
+    }
+}
+
+/// Tasks pushed into [`Shared`] queues.
+static PUSHES: AtomicUsize = AtomicUsize::new(0);
+
+/// A queue of tasks shared with other runners.
+#[derive(Default, Clone)]
+struct Shared(Arc<Mutex<VecDeque<Runnable>>>);
+
+impl Shared {
+    fn push(&self, task: Runnable) {
+        PUSHES.fetch_add(1, Ordering::SeqCst);
+        self.0.lock().unwrap().push_back(task);
+    }
+
+    fn pop(&self) -> Option<Runnable> {
+        self.0.lock().unwrap().pop_front()
+    }
+
+    fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+}
+
+impl TaskQueue for Shared {
+    type Local = Local;
+
+    fn push(&self, task: Runnable, _key: u64) {
+        Shared::push(self, task)
+    }
+
+    fn pop(&self) -> Option<Runnable> {
+        Shared::pop(self)
+    }
+
+    fn local(&self) -> Local {
+        Local(Shared::default())
+    }
+}
+
+/// A local queue of up to 16 tasks that other runners steal from one task at a time.
+struct Local(Shared);
+
+impl LocalTaskQueue for Local {
+    type Global = Shared;
+    type Handle = Shared;
+
+    fn push(&mut self, task: Runnable, _key: u64) -> Result<(), Runnable> {
+        if self.0.len() >= 16 {
+            return Err(task);
+        }
+        self.0.push(task);
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Option<Runnable> {
+        self.0.pop()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.len() == 0
+    }
+
+    fn append(&mut self, other: &mut Local, global: &Shared) -> bool {
+        let mut spilled = false;
+        while let Some(task) = other.0.pop() {
+            if let Err(task) = LocalTaskQueue::push(self, task, 0) {
+                self.spill(global);
+                self.0.push(task);
+                spilled = true;
+            }
+        }
+        spilled
+    }
+
+    fn spill(&mut self, global: &Shared) {
+        for _ in 0..self.0.len() / 2 {
+            if let Some(task) = self.0.pop() {
+                global.push(task);
+            }
+        }
+    }
+
+    fn flush(&mut self, global: &Shared) {
+        while let Some(task) = self.0.pop() {
+            global.push(task);
+        }
+    }
+
+    fn steal_global(&mut self, global: &Shared) {
+        if let Some(task) = global.pop() {
+            self.0.push(task);
+        }
+    }
+
+    fn steal_local(&mut self, other: &Shared) {
+        if let Some(task) = other.pop() {
+            self.0.push(task);
+        }
+    }
+
+    fn handle(&self) -> Shared {
+        self.0.clone()
+    }
+}
+
+#[test]
+fn custom_queue_runs_tasks() {
+    static EX: Executor<'_> = Builder::new()
+        .custom_queue::<Shared>(QueueKind::Crossbeam)
+        .build();
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+
+    Parallel::new()
+        .each(0..4, |_| future::block_on(EX.run(shutdown.recv())))
+        .finish(|| {
+            let task = EX.spawn(async {
+                let tasks: Vec<_> = (0..1000).map(|i| EX.spawn(async move { i })).collect();
+                let mut sum = 0;
+                for task in tasks {
+                    sum += task.await;
+                }
+                sum
+            });
+            assert_eq!(future::block_on(task), (0..1000).sum());
+            drop(signal);
+        });
+    assert!(PUSHES.load(Ordering::SeqCst) >= 1001);
+}
+
+#[test]
+fn woken_tasks_in_heap_run_on_other_runners() {
+    static EX: Executor<'_> = Builder::new().queue(QueueKind::Priority).build();
+    static WAITING: AtomicUsize = AtomicUsize::new(0);
+    static RAN: Mutex<Vec<ThreadId>> = Mutex::new(Vec::new());
+
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (s, r) = async_channel::unbounded::<()>();
+
+    let woken: Vec<_> = (0..3)
+        .map(|_| {
+            let r = r.clone();
+            EX.spawn(async move {
+                WAITING.fetch_add(1, Ordering::SeqCst);
+                r.recv().await.unwrap();
+                RAN.lock().unwrap().push(thread::current().id());
+            })
+        })
+        .collect();
+
+    Parallel::new()
+        .each(0..2, |_| future::block_on(EX.run(shutdown.recv())))
+        .finish(|| {
+            let blocking = EX.spawn(async move {
+                // Let the other tasks start waiting first.
+                while WAITING.load(Ordering::SeqCst) < 3 {
+                    future::yield_now().await;
+                }
+
+                // Wake the tasks on this thread, which puts them in the heap, and then block it.
+                for _ in 0..3 {
+                    s.try_send(()).unwrap();
+                }
+
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while RAN.lock().unwrap().len() < 3 && Instant::now() < deadline {
+                    thread::yield_now();
+                }
+                thread::current().id()
+            });
+
+            let blocked_on = future::block_on(blocking);
+            let ran = RAN.lock().unwrap().clone();
+            assert_eq!(ran.len(), 3);
+            assert!(ran.iter().all(|&id| id != blocked_on));
+            for task in woken {
+                future::block_on(task);
+            }
+            drop(signal);
+        });
+}
+
+#[test]
+fn higher_priority_runs_first() {
+    let ex = Builder::new().queue(QueueKind::Priority).build();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    let tasks: Vec<_> = [1, 3, 0, 2, 3]
+        .iter()
+        .enumerate()
+        .map(|(i, &priority)| {
+            let order = order.clone();
+            ex.spawn_with_priority(priority, async move {
+                order.lock().unwrap().push(i);
+            })
+        })
+        .collect();
+
+    while ex.try_tick() {}
+    for task in tasks {
+        future::block_on(task);
+    }
+
+    // Tasks of equal priority run in the order they were spawned.
+    assert_eq!(*order.lock().unwrap(), [1, 4, 3, 0, 2]);
+}
+
+#[test]
+fn earliest_deadline_runs_first() {
+    let ex = Builder::new().queue(QueueKind::Deadline).build();
+    let order = Arc::new(Mutex::new(Vec::new()));
+
+    let now = Instant::now();
+    let mut tasks: Vec<_> = [30, 10, 20, 10]
+        .iter()
+        .enumerate()
+        .map(|(i, &secs)| {
+            let order = order.clone();
+            ex.spawn_with_deadline(now + Duration::from_secs(secs), async move {
+                order.lock().unwrap().push(i);
+            })
+        })
+        .collect();
+
+    // Tasks without a deadline run last.
+    let order2 = order.clone();
+    tasks.push(ex.spawn(async move { order2.lock().unwrap().push(4) }));
+
+    while ex.try_tick() {}
+    for task in tasks {
+        future::block_on(task);
+    }
+    assert_eq!(*order.lock().unwrap(), [1, 3, 2, 0, 4]);
+
+    let metrics = ex.deadline_metrics();
+    assert_eq!(metrics.met, 4);
+    assert_eq!(metrics.missed, 0);
+}
+
+#[test]
+fn missed_deadlines_are_counted() {
+    let ex = Executor::new();
+    let (s, r) = async_channel::bounded::<()>(1);
+
+    let late = ex.spawn_with_deadline(Instant::now() + Duration::from_millis(10), async move {
+        r.recv().await.unwrap();
+    });
+    assert!(ex.try_tick());
+
+    thread::sleep(Duration::from_millis(20));
+    s.try_send(()).unwrap();
+    assert!(ex.try_tick());
+    future::block_on(late);
+
+    let metrics = ex.deadline_metrics();
+    assert_eq!(metrics.met, 0);
+    assert_eq!(metrics.missed, 1);
+    assert!(metrics.max_lateness >= Duration::from_millis(10));
+    assert_eq!(metrics.total_lateness, metrics.max_lateness);
+}
@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_executor::{Executor, RestartStrategy, Supervisor};
+use futures_lite::future;
+
+/// Counts how many times each of three children started, with the second one failing once.
+fn starts(strategy: RestartStrategy) -> [usize; 3] {
+    let counts = [
+        AtomicUsize::new(0),
+        AtomicUsize::new(0),
+        AtomicUsize::new(0),
+    ];
+    let (s, r) = async_channel::unbounded::<()>();
+    let ex = Executor::new();
+
+    // The first and last children wait until the second has completed.
+    let waiting = |i: usize| {
+        let counts = &counts;
+        let r = r.clone();
+        move || {
+            counts[i].fetch_add(1, Ordering::SeqCst);
+            let r = r.clone();
+            async move { r.recv().await.map_err(|e| e.to_string()) }
+        }
+    };
+    let sup = Supervisor::new(strategy)
+        .child(waiting(0))
+        .child(|| async {
+            if counts[1].fetch_add(1, Ordering::SeqCst) == 0 {
+                return Err("first start fails");
+            }
+            s.send(()).await.unwrap();
+            s.send(()).await.unwrap();
+            Ok(())
+        })
+        .child(waiting(2));
+
+    ex.block_on(sup.run(&ex)).unwrap();
+    [
+        counts[0].load(Ordering::SeqCst),
+        counts[1].load(Ordering::SeqCst),
+        counts[2].load(Ordering::SeqCst),
+    ]
+}
+
+#[test]
+fn strategies_restart_the_right_children() {
+    assert_eq!(starts(RestartStrategy::OneForOne), [1, 2, 1]);
+    assert_eq!(starts(RestartStrategy::OneForAll), [2, 2, 2]);
+    assert_eq!(starts(RestartStrategy::RestForOne), [1, 2, 2]);
+}
+
+#[test]
+fn gives_up_after_max_restarts() {
+    let starts = AtomicUsize::new(0);
+    let ex = Executor::new();
+    let sup = Supervisor::new(RestartStrategy::OneForOne)
+        .max_restarts(2, Duration::from_secs(60))
+        .backoff(Duration::from_millis(10), Duration::from_millis(15))
+        .child(|| async {
+            starts.fetch_add(1, Ordering::SeqCst);
+            panic!("boom");
+            #[allow(unreachable_code)]
+            Ok::<(), String>(())
+        });
+
+    // Restarts wait for 10ms, then 15ms.
+    let start = Instant::now();
+    let failure = ex.block_on(sup.run(&ex)).unwrap_err();
+    assert!(start.elapsed() >= Duration::from_millis(25));
+
+    assert!(failure.panicked);
+    assert_eq!(failure.error.to_string(), "boom");
+    assert_eq!(starts.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn backoffs_of_two_supervisors_overlap() {
+    let starts = [Mutex::new(Vec::new()), Mutex::new(Vec::new())];
+    let ex = Executor::new();
+
+    // Each child fails twice, so its supervisor waits for 100ms, then 200ms.
+    let supervisor = |i: usize| {
+        let starts = &starts[i];
+        Supervisor::new(RestartStrategy::OneForOne)
+            .backoff(Duration::from_millis(100), Duration::from_millis(200))
+            .child(move || async move {
+                let mut starts = starts.lock().unwrap();
+                starts.push(Instant::now());
+                if starts.len() < 3 {
+                    return Err("not yet");
+                }
+                Ok(())
+            })
+    };
+    let (sup1, sup2) = (supervisor(0), supervisor(1));
+
+    let start = Instant::now();
+    let (r1, r2) = ex.block_on(future::zip(sup1.run(&ex), sup2.run(&ex)));
+    r1.unwrap();
+    r2.unwrap();
+    let elapsed = start.elapsed();
+
+    for starts in &starts {
+        let starts = starts.lock().unwrap();
+        assert_eq!(starts.len(), 3);
+        assert!(starts[1] - starts[0] >= Duration::from_millis(100));
+        assert!(starts[2] - starts[1] >= Duration::from_millis(200));
+    }
+
+    // Both supervisors waited at the same time rather than one after the other.
+    assert!(elapsed >= Duration::from_millis(300));
+    assert!(elapsed < Duration::from_millis(600));
+}
+
+#[test]
+fn nested_supervisor_gets_restarted() {
+    let starts = AtomicUsize::new(0);
+    let ex = Executor::new();
+
+    let inner = Supervisor::new(RestartStrategy::OneForOne)
+        .max_restarts(0, Duration::from_secs(60))
+        .child(|| async {
+            match starts.fetch_add(1, Ordering::SeqCst) {
+                0 => Err("give up"),
+                _ => Ok(()),
+            }
+        });
+    let sup = Supervisor::new(RestartStrategy::OneForOne).supervisor(inner);
+
+    ex.block_on(sup.run(&ex)).unwrap();
+    assert_eq!(starts.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn cancelling_cancels_the_subtree() {
+    let ex = Executor::new();
+    let dropped = Arc::new(AtomicUsize::new(0));
+
+    struct Guard(Arc<AtomicUsize>);
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+    let child = || {
+        let dropped = dropped.clone();
+        move || {
+            let guard = Guard(dropped.clone());
+            async move {
+                let _guard = guard;
+                future::pending::<Result<(), String>>().await
+            }
+        }
+    };
+
+    let inner = Supervisor::new(RestartStrategy::OneForOne)
+        .child(child())
+        .child(child());
+    let sup = Supervisor::new(RestartStrategy::OneForAll)
+        .child(child())
+        .supervisor(inner);
+    let handle = sup.handle();
+
+    ex.block_on(future::zip(sup.run(&ex), async {
+        // Let the children start.
+        for _ in 0..10 {
+            future::yield_now().await;
+        }
+        assert!(!ex.is_empty());
+        handle.cancel();
+    }))
+    .0
+    .unwrap();
+
+    // The executor drops the futures of cancelled tasks.
+    while ex.try_tick() {}
+    assert!(ex.is_empty());
+    assert_eq!(dropped.load(Ordering::SeqCst), 3);
+}
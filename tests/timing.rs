@@ -0,0 +1,66 @@
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use async_executor::{Builder, TaskTimes};
+use futures_lite::future;
+
+#[test]
+fn cpu_time_excludes_sleep() {
+    let ex = Builder::new().time_tasks(true).build();
+
+    let busy = ex.spawn(async {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_millis(20) {}
+        future::yield_now().await;
+    });
+    let sleepy = ex.spawn(async {
+        thread::sleep(Duration::from_millis(20));
+        future::yield_now().await;
+    });
+    assert!(ex.try_tick());
+    assert!(ex.try_tick());
+
+    let mut times = ex.task_times();
+    times.sort_by_key(|t| t.id);
+    assert_eq!(times.len(), 2);
+    assert_eq!(times[0].polls, 1);
+    assert_eq!(times[1].polls, 1);
+    assert!(times[0].wall_time >= Duration::from_millis(20));
+    assert!(times[1].wall_time >= Duration::from_millis(20));
+    if cfg!(target_os = "linux") {
+        // Other processes may take the CPU away from the busy task, so only compare the two.
+        assert!(times[0].cpu_time.unwrap() > times[1].cpu_time.unwrap());
+    }
+
+    future::block_on(ex.run(async {
+        busy.await;
+        sleepy.await;
+    }));
+    assert!(ex.task_times().is_empty());
+}
+
+#[test]
+fn completed_tasks_are_reported() {
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let hook = {
+        let reported = reported.clone();
+        Arc::new(move |times: &TaskTimes| reported.lock().unwrap().push(*times))
+    };
+    let ex = Builder::new().on_task_complete(hook).build();
+
+    let done = ex.spawn(async {
+        future::yield_now().await;
+        future::yield_now().await;
+    });
+    let cancelled = ex.spawn(future::pending::<()>());
+    future::block_on(ex.run(done));
+
+    // Cancelling a task schedules it to be dropped.
+    drop(cancelled);
+    while ex.try_tick() {}
+
+    let reported = reported.lock().unwrap();
+    assert_eq!(reported.len(), 1);
+    assert_eq!(reported[0].polls, 3);
+}
@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant};
+
+use async_executor::Executor;
+use easy_parallel::Parallel;
+use futures_lite::{future, prelude::*};
+
+#[test]
+fn nested_run_does_not_steal_outer_tasks() {
+    static OUTER: Executor<'_> = Executor::new();
+    static RAN: AtomicBool = AtomicBool::new(false);
+
+    thread_local! {
+        static NESTED: Cell<bool> = const { Cell::new(false) };
+    }
+
+    let (s, r) = async_channel::bounded::<()>(1);
+    let woken = OUTER.spawn(async move {
+        r.recv().await.unwrap();
+        assert!(!NESTED.with(Cell::get));
+        RAN.store(true, Ordering::SeqCst);
+    });
+
+    let blocking = OUTER.spawn(async move {
+        // Let the other task start waiting first.
+        future::yield_now().await;
+
+        let inner = Executor::new();
+        NESTED.with(|n| n.set(true));
+        future::block_on(inner.run(async {
+            // Wake a task of the outer executor from inside the inner executor.
+            s.send(()).await.unwrap();
+            for _ in 0..10 {
+                future::yield_now().await;
+            }
+        }));
+        NESTED.with(|n| n.set(false));
+
+        assert!(!RAN.load(Ordering::SeqCst));
+    });
+
+    future::block_on(OUTER.run(async {
+        blocking.await;
+        woken.await;
+    }));
+    assert!(RAN.load(Ordering::SeqCst));
+}
+
+#[test]
+fn two_executors_on_one_thread() {
+    let ex1 = Executor::new();
+    let ex2 = Executor::new();
+
+    let (s1, r1) = async_channel::bounded::<usize>(1);
+    let (s2, r2) = async_channel::bounded::<usize>(1);
+
+    // Tasks on each executor wake tasks on the other one.
+    let t1 = ex1.spawn(async move {
+        for i in 0..100 {
+            s2.send(i).await.unwrap();
+            assert_eq!(r1.recv().await.unwrap(), i + 1);
+        }
+    });
+    let t2 = ex2.spawn(async move {
+        while let Ok(i) = r2.recv().await {
+            s1.send(i + 1).await.ok();
+        }
+    });
+
+    future::block_on(ex1.run(t1).or(ex2.run(async {
+        t2.await;
+        future::pending().await
+    })));
+}
+
+#[test]
+fn woken_tasks_are_stolen_during_long_poll() {
+    static EX: Executor<'_> = Executor::new();
+    static WAITING: AtomicUsize = AtomicUsize::new(0);
+    static RAN: AtomicUsize = AtomicUsize::new(0);
+
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (s, r) = async_channel::unbounded::<()>();
+
+    let woken: Vec<_> = (0..2)
+        .map(|_| {
+            let r = r.clone();
+            EX.spawn(async move {
+                WAITING.fetch_add(1, Ordering::SeqCst);
+                r.recv().await.unwrap();
+                RAN.fetch_add(1, Ordering::SeqCst);
+            })
+        })
+        .collect();
+
+    Parallel::new()
+        .each(0..2, |_| future::block_on(EX.run(shutdown.recv())))
+        .finish(|| {
+            let blocking = EX.spawn(async move {
+                // Let the other tasks start waiting first.
+                while WAITING.load(Ordering::SeqCst) < 2 {
+                    future::yield_now().await;
+                }
+
+                // Wake both tasks on this thread and then block it for a while.
+                s.try_send(()).unwrap();
+                s.try_send(()).unwrap();
+
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while RAN.load(Ordering::SeqCst) < 2 && Instant::now() < deadline {
+                    thread::yield_now();
+                }
+                RAN.load(Ordering::SeqCst)
+            });
+
+            assert_eq!(future::block_on(blocking), 2);
+            for task in woken {
+                future::block_on(task);
+            }
+            drop(signal);
+        });
+}
+
+#[test]
+fn single_woken_task_is_stolen_during_long_poll() {
+    static EX: Executor<'_> = Executor::new();
+    static WAITING: AtomicBool = AtomicBool::new(false);
+    static RAN_ON: Mutex<Option<ThreadId>> = Mutex::new(None);
+
+    let (signal, shutdown) = async_channel::unbounded::<()>();
+    let (s, r) = async_channel::unbounded::<()>();
+
+    let woken = EX.spawn(async move {
+        WAITING.store(true, Ordering::SeqCst);
+        r.recv().await.unwrap();
+        *RAN_ON.lock().unwrap() = Some(thread::current().id());
+    });
+
+    Parallel::new()
+        .each(0..2, |_| future::block_on(EX.run(shutdown.recv())))
+        .finish(|| {
+            let blocking = EX.spawn(async move {
+                // Let the other task start waiting first.
+                while !WAITING.load(Ordering::SeqCst) {
+                    future::yield_now().await;
+                }
+
+                // Wake the task on this thread and then block it for a while.
+                s.try_send(()).unwrap();
+
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while RAN_ON.lock().unwrap().is_none() && Instant::now() < deadline {
+                    thread::yield_now();
+                }
+                thread::current().id()
+            });
+
+            let blocked_on = future::block_on(blocking);
+            let ran_on = RAN_ON.lock().unwrap().expect("the woken task didn't run");
+            assert_ne!(ran_on, blocked_on);
+            future::block_on(woken);
+            drop(signal);
+        });
+}
+
+#[test]
+fn spawn_burst_spills_to_global_queue() {
+    let ex = Executor::new();
+
+    future::block_on(ex.run(async {
+        let tasks: Vec<_> = (0..1000).map(|i| ex.spawn(async move { i })).collect();
+
+        // The tasks don't all fit in this runner's queues, so some of them are up for grabs.
+        let mut ticks = 0;
+        while ex.try_tick() {
+            ticks += 1;
+        }
+        assert!(ticks > 0);
+
+        for (i, task) in tasks.into_iter().enumerate() {
+            assert_eq!(task.await, i);
+        }
+    }));
+}
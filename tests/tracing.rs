@@ -0,0 +1,133 @@
+#![cfg(feature = "tracing")]
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use async_executor::Executor;
+use futures_lite::future;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+/// The name of task spans.
+const TASK: &str = if cfg!(feature = "console") {
+    "runtime.spawn"
+} else {
+    "task"
+};
+
+/// A span seen by the subscriber.
+#[derive(Debug, Clone)]
+struct SpanData {
+    name: &'static str,
+    parent: Option<u64>,
+    fields: HashMap<&'static str, String>,
+}
+
+/// Records spans and events, for a single thread.
+#[derive(Default, Clone)]
+struct Recorder(Arc<Log>);
+
+#[derive(Default)]
+struct Log {
+    spans: Mutex<Vec<SpanData>>,
+    stack: Mutex<Vec<u64>>,
+    events: Mutex<Vec<String>>,
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), format!("{:?}", value));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>) -> Id {
+        let parent = match attrs.parent() {
+            Some(id) => Some(id.into_u64()),
+            None if attrs.is_contextual() => self.0.stack.lock().unwrap().last().copied(),
+            None => None,
+        };
+        let mut fields = HashMap::new();
+        attrs.record(&mut Fields(&mut fields));
+
+        let mut spans = self.0.spans.lock().unwrap();
+        spans.push(SpanData {
+            name: attrs.metadata().name(),
+            parent,
+            fields,
+        });
+        Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = HashMap::new();
+        event.record(&mut Fields(&mut fields));
+        self.0
+            .events
+            .lock()
+            .unwrap()
+            .push(fields.remove("message").unwrap_or_default());
+    }
+
+    fn enter(&self, span: &Id) {
+        self.0.stack.lock().unwrap().push(span.into_u64());
+    }
+
+    fn exit(&self, _: &Id) {
+        self.0.stack.lock().unwrap().pop();
+    }
+}
+
+#[test]
+fn tasks_get_spans() {
+    let recorder = Recorder::default();
+
+    let line = tracing::subscriber::with_default(recorder.clone(), || {
+        let ex = Executor::new();
+        let request = tracing::info_span!("request").entered();
+        let line = line!() + 1;
+        let task = ex.group("db").spawn(async {
+            tracing::info!("inside task");
+            future::yield_now().await;
+        });
+        drop(request);
+
+        future::block_on(ex.run(task));
+        drop(ex);
+        line
+    });
+
+    let spans = recorder.0.spans.lock().unwrap();
+    let id = 1 + spans.iter().position(|s| s.name == TASK).unwrap() as u64;
+    let task = &spans[id as usize - 1];
+
+    // The task's span is a child of the span it was spawned in.
+    assert_eq!(spans[task.parent.unwrap() as usize - 1].name, "request");
+    assert_eq!(task.fields["task.name"], "\"db\"");
+    assert_eq!(task.fields["task.id"], "0");
+    assert!(task.fields["loc.file"].contains("tracing.rs"));
+    assert_eq!(task.fields["loc.line"], line.to_string());
+
+    // Both polls are children of the task's span.
+    let polls = spans
+        .iter()
+        .filter(|s| s.name == "poll" && s.parent == Some(id))
+        .count();
+    assert_eq!(polls, 2);
+
+    let events = recorder.0.events.lock().unwrap();
+    assert!(events.iter().any(|e| e == "inside task"));
+    assert!(events.iter().any(|e| e == "executor shutting down"));
+}
@@ -0,0 +1,59 @@
+use std::task::Poll;
+use std::thread;
+use std::time::Duration;
+
+use async_executor::Builder;
+use futures_lite::future;
+
+#[test]
+fn wakes_are_counted() {
+    let ex = Builder::new().track_wakes(true).build();
+
+    let (s, r) = async_channel::bounded::<()>(1);
+    let task = ex.spawn(async move {
+        future::yield_now().await;
+        r.recv().await.unwrap();
+    });
+
+    while ex.try_tick() {}
+    let wakes = ex.task_wakes();
+    assert_eq!(wakes[0].wakes, 1);
+    assert_eq!(wakes[0].self_wakes, 1);
+    assert!(wakes[0].waiting.is_some());
+
+    // A wake from outside the task is not a self-wake.
+    s.try_send(()).unwrap();
+    let wakes = ex.task_wakes();
+    assert_eq!(wakes[0].wakes, 2);
+    assert_eq!(wakes[0].self_wakes, 1);
+    assert!(wakes[0].waiting.is_none());
+
+    future::block_on(ex.run(task));
+    assert!(ex.task_wakes().is_empty());
+}
+
+#[test]
+fn tasks_never_woken_are_suspected() {
+    let ex = Builder::new().track_wakes(true).build();
+
+    // This task forgets to store its waker, so nothing will ever wake it.
+    let lost = ex.spawn(future::poll_fn(|_| Poll::<()>::Pending));
+
+    let (s, r) = async_channel::bounded::<()>(1);
+    let woken = ex.spawn(async move { r.recv().await.unwrap() });
+
+    while ex.try_tick() {}
+    thread::sleep(Duration::from_millis(10));
+    s.try_send(()).unwrap();
+
+    let suspects = ex.suspected_lost_wakeups(Duration::from_millis(10));
+    assert_eq!(suspects.len(), 1);
+    assert_eq!(suspects[0].id, 0);
+    assert_eq!(suspects[0].wakes, 0);
+    assert!(ex
+        .suspected_lost_wakeups(Duration::from_secs(60))
+        .is_empty());
+
+    future::block_on(ex.run(woken));
+    drop(lost);
+}